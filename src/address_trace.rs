@@ -0,0 +1,148 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Runs caches on their own against a sequence of addresses, without a program.
+//!
+//! Addresses are read either from Dinero `din` files (as written by `--din`) or from the CSV
+//! written by `--trace --trace-format csv`, which gives the fetch of each instruction followed
+//! by its load or store.
+
+use crate::cache::{AccessKind, Cache};
+use crate::error::SimError;
+use crate::memory::{Hierarchy, MemoryLevel};
+use crate::trace::{BYTES_PER_WORD, CSV_HEADER};
+use crate::NUM_MEMORY;
+
+/// Reads an address trace, as CSV if it starts with the `--trace` CSV header and `din`
+/// otherwise. Addresses are returned as word addresses.
+pub fn parse_trace(source: &str) -> Result<Vec<(AccessKind, usize)>, SimError> {
+  match source.lines().next() {
+    Some(line) if line.trim() == CSV_HEADER => parse_csv(source),
+    _ => parse_din(source),
+  }
+}
+
+/// Reads a `din` trace: an access type (0 for a read, 1 for a write, 2 for an instruction
+/// fetch) and a hex byte address on each line. Anything after the address is ignored, as are
+/// blank lines.
+pub fn parse_din(source: &str) -> Result<Vec<(AccessKind, usize)>, SimError> {
+  let mut accesses = Vec::new();
+  for (idx, line) in source.lines().enumerate() {
+    let bad_line = |message: String| SimError::BadTrace {
+      line: idx + 1,
+      message,
+    };
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (label, address) = match fields[..] {
+      [] => continue,
+      [label, address, ..] => (label, address),
+      _ => return Err(bad_line(format!("has no address: {:?}", line))),
+    };
+
+    let kind = match label {
+      "0" => AccessKind::Load,
+      "1" => AccessKind::Store,
+      "2" => AccessKind::InstructionFetch,
+      _ => {
+        return Err(bad_line(format!(
+          "has an access type other than 0, 1 or 2: {:?}",
+          line
+        )))
+      }
+    };
+    let digits = address.trim_start_matches("0x");
+    let bytes = usize::from_str_radix(digits, 16)
+      .map_err(|_| bad_line(format!("has an address that isn't hex: {:?}", line)))?;
+    if bytes % BYTES_PER_WORD != 0 {
+      return Err(bad_line(format!(
+        "has an address that isn't word aligned: {:?}",
+        line
+      )));
+    }
+    accesses.push((kind, word_address(bytes / BYTES_PER_WORD, idx)?));
+  }
+  Ok(accesses)
+}
+
+/// Reads the CSV written by `--trace --trace-format csv`, giving the fetch of each instruction
+/// followed by its load or store
+pub fn parse_csv(source: &str) -> Result<Vec<(AccessKind, usize)>, SimError> {
+  let columns: Vec<&str> = CSV_HEADER.split(',').collect();
+  let column = |name: &str| columns.iter().position(|column| *column == name).unwrap();
+  let (pc, access, address) = (column("pc"), column("access"), column("address"));
+
+  let mut accesses = Vec::new();
+  for (idx, line) in source.lines().enumerate().skip(1) {
+    if line.trim().is_empty() {
+      continue;
+    }
+    let bad_line = |message: &str| SimError::BadTrace {
+      line: idx + 1,
+      message: format!("{}: {:?}", message, line),
+    };
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != columns.len() {
+      return Err(bad_line(&format!("doesn't have {} fields", columns.len())));
+    }
+
+    let fetch = fields[pc]
+      .parse()
+      .map_err(|_| bad_line("has a pc that isn't a number"))?;
+    accesses.push((AccessKind::InstructionFetch, word_address(fetch, idx)?));
+    let kind = match fields[access] {
+      "" => continue,
+      "load" => AccessKind::Load,
+      "store" => AccessKind::Store,
+      _ => return Err(bad_line("has an access other than load or store")),
+    };
+    let data = fields[address]
+      .parse()
+      .map_err(|_| bad_line("has an address that isn't a number"))?;
+    accesses.push((kind, word_address(data, idx)?));
+  }
+  Ok(accesses)
+}
+
+fn word_address(address: usize, idx: usize) -> Result<usize, SimError> {
+  if address >= NUM_MEMORY {
+    return Err(SimError::BadTrace {
+      line: idx + 1,
+      message: format!("has address {} which is outside of memory", address),
+    });
+  }
+  Ok(address)
+}
+
+/// Sends every access through the caches (ordered from L1 down) to `memory`, instruction
+/// fetches going through `instruction_cache` instead of the L1 when there is one. Then every
+/// cache is flushed, as if the machine had halted. The trace doesn't say what was stored, so
+/// stores write 0.
+pub fn run_trace(
+  accesses: &[(AccessKind, usize)],
+  caches: &mut [Cache],
+  mut instruction_cache: Option<&mut Cache>,
+  memory: &mut dyn MemoryLevel,
+) {
+  let (cache, lower) = caches
+    .split_first_mut()
+    .expect("an address trace needs at least one cache");
+  let mut memory = Hierarchy {
+    caches: lower,
+    memory,
+  };
+
+  for (kind, address) in accesses.iter().copied() {
+    match kind {
+      AccessKind::InstructionFetch => {
+        let fetch_cache = instruction_cache.as_deref_mut().unwrap_or(&mut *cache);
+        fetch_cache.get_value(address, &mut memory, kind);
+      }
+      AccessKind::Load => {
+        cache.get_value(address, &mut memory, kind);
+      }
+      AccessKind::Store => cache.set_value(address, &mut memory, 0),
+    }
+  }
+
+  cache.flush(&mut memory);
+  memory.flush();
+}
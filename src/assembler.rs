@@ -0,0 +1,355 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! A two-pass LC3100 assembler mirroring the provided `tests/LC3101a.c`.
+//!
+//! The first pass validates every line and builds the symbol table, the second pass
+//! encodes each line with labels resolved. Error checks (and the order they happen in)
+//! match the C assembler so the same source produces the same result.
+
+use std::fmt;
+
+const MAX_LINE_LENGTH: usize = 1000;
+const MAX_NUM_LABELS: usize = 65536;
+// Unlike the C assembler this does not include a null terminator
+const MAX_LABEL_LENGTH: usize = 6;
+
+const OPCODES: [&str; 9] = [
+  "add", "nand", "lw", "sw", "beq", "jalr", "halt", "noop", ".fill",
+];
+
+/// An assembled program, the machine code words along with the labels defined in the source
+pub struct Program {
+  pub words: Vec<i32>,
+  /// Labels in the order they were defined, paired with the address they refer to
+  pub labels: Vec<(String, usize)>,
+}
+
+#[derive(Debug)]
+pub enum AssembleError {
+  LineTooLong,
+  UnrecognizedOpcode { opcode: String, address: usize },
+  RegisterOutOfRange,
+  BadRegisterCharacter,
+  BadAddressCharacter,
+  NotEnoughArguments { address: usize },
+  LabelTooLong,
+  LabelStartsWithNonLetter,
+  LabelHasBadCharacter,
+  DuplicateLabel { label: String, address: usize },
+  TooManyLabels { label: String },
+  MissingLabel { label: String },
+  OffsetOutOfRange { offset: i64 },
+}
+
+/// The fields of a single line of assembly, empty strings are used for missing fields
+struct Line<'a> {
+  label: &'a str,
+  opcode: &'a str,
+  arg0: &'a str,
+  arg1: &'a str,
+  arg2: &'a str,
+}
+
+impl Program {
+  /// Looks up the address of a label defined in the source
+  pub fn label_address(&self, label: &str) -> Option<usize> {
+    self
+      .labels
+      .iter()
+      .find(|(name, _)| name == label)
+      .map(|(_, address)| *address)
+  }
+
+  /// Formats the machine code the same way the C assembler writes its output file
+  pub fn to_machine_code(&self) -> String {
+    let mut output = String::new();
+    for word in self.words.iter() {
+      output.push_str(&format!("{}\n", word));
+    }
+    output
+  }
+}
+
+impl fmt::Display for AssembleError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AssembleError::LineTooLong => write!(f, "error: line too long"),
+      AssembleError::UnrecognizedOpcode { opcode, address } => write!(
+        f,
+        "error: unrecognized opcode {} at address {}",
+        opcode, address
+      ),
+      AssembleError::RegisterOutOfRange => write!(f, "error: register out of range"),
+      AssembleError::BadRegisterCharacter => write!(f, "bad character in register argument"),
+      AssembleError::BadAddressCharacter => write!(f, "bad character in addressField"),
+      AssembleError::NotEnoughArguments { address } => {
+        write!(f, "error at address {}: not enough arguments", address)
+      }
+      AssembleError::LabelTooLong => write!(f, "label too long"),
+      AssembleError::LabelStartsWithNonLetter => write!(f, "label doesn't start with letter"),
+      AssembleError::LabelHasBadCharacter => {
+        write!(f, "label has character other than letters and numbers")
+      }
+      AssembleError::DuplicateLabel { label, address } => {
+        write!(f, "error: duplicate label {} at address {}", label, address)
+      }
+      AssembleError::TooManyLabels { label } => {
+        write!(f, "error: too many labels (label={})", label)
+      }
+      AssembleError::MissingLabel { label } => write!(f, "error: missing label {}", label),
+      AssembleError::OffsetOutOfRange { offset } => {
+        write!(f, "error: offset {} out of range", offset)
+      }
+    }
+  }
+}
+
+/// Assembles LC3100 assembly source into machine code
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+  let lines = split_lines(source)?;
+
+  // First pass: validate every line and map labels to addresses
+  let mut labels: Vec<(String, usize)> = Vec::new();
+  for (address, line) in lines.iter().enumerate() {
+    check_line(line, address)?;
+
+    if line.label.is_empty() {
+      continue;
+    }
+    check_label(line.label)?;
+    if labels.iter().any(|(name, _)| name == line.label) {
+      return Err(AssembleError::DuplicateLabel {
+        label: line.label.to_string(),
+        address,
+      });
+    }
+    if labels.len() >= MAX_NUM_LABELS {
+      return Err(AssembleError::TooManyLabels {
+        label: line.label.to_string(),
+      });
+    }
+    labels.push((line.label.to_string(), address));
+  }
+
+  // Second pass: encode each line with the symbols filled in as addresses
+  let mut words = Vec::with_capacity(lines.len());
+  for (address, line) in lines.iter().enumerate() {
+    words.push(encode_line(line, address, &labels)?);
+  }
+
+  Ok(Program { words, labels })
+}
+
+/// If `word` is one of the opcodes (or `.fill`) the assembler understands
+pub fn is_opcode(word: &str) -> bool {
+  OPCODES.contains(&word)
+}
+
+/// Splits the source into lines and parses out their fields
+fn split_lines(source: &str) -> Result<Vec<Line<'_>>, AssembleError> {
+  let mut lines = Vec::new();
+  for raw_line in source.split_inclusive('\n') {
+    // fgets in the C assembler fills its buffer on lines this long
+    if raw_line.len() >= MAX_LINE_LENGTH - 1 {
+      return Err(AssembleError::LineTooLong);
+    }
+    lines.push(parse_line(raw_line));
+  }
+  Ok(lines)
+}
+
+fn parse_line(raw_line: &str) -> Line<'_> {
+  // A label is anything at the very start of the line up to the first space, tab or newline
+  let label_end = raw_line.find([' ', '\t', '\n']).unwrap_or(raw_line.len());
+  let (label, rest) = raw_line.split_at(label_end);
+
+  let mut fields = rest
+    .split([' ', '\t', '\n', '\r'])
+    .filter(|field| !field.is_empty());
+
+  Line {
+    label,
+    opcode: fields.next().unwrap_or(""),
+    arg0: fields.next().unwrap_or(""),
+    arg1: fields.next().unwrap_or(""),
+    arg2: fields.next().unwrap_or(""),
+  }
+}
+
+/// Performs the first pass checks on the opcode and its arguments
+fn check_line(line: &Line, address: usize) -> Result<(), AssembleError> {
+  if !OPCODES.contains(&line.opcode) {
+    return Err(AssembleError::UnrecognizedOpcode {
+      opcode: line.opcode.to_string(),
+      address,
+    });
+  }
+
+  if matches!(line.opcode, "add" | "nand" | "lw" | "sw" | "beq" | "jalr") {
+    check_register(line.arg0)?;
+    check_register(line.arg1)?;
+  }
+  if matches!(line.opcode, "add" | "nand") {
+    check_register(line.arg2)?;
+  }
+
+  if matches!(line.opcode, "lw" | "sw" | "beq") {
+    check_address(line.arg2)?;
+  }
+  if line.opcode == ".fill" {
+    check_address(line.arg0)?;
+  }
+
+  let not_enough_arguments = match line.opcode {
+    "halt" | "noop" => false,
+    "jalr" => line.arg1.is_empty(),
+    ".fill" => line.arg0.is_empty(),
+    _ => line.arg2.is_empty(),
+  };
+  if not_enough_arguments {
+    return Err(AssembleError::NotEnoughArguments { address });
+  }
+
+  Ok(())
+}
+
+fn check_register(arg: &str) -> Result<(), AssembleError> {
+  let register = atoi(arg);
+  if !(0..=7).contains(&register) {
+    return Err(AssembleError::RegisterOutOfRange);
+  }
+  if !is_strict_number(arg) {
+    return Err(AssembleError::BadRegisterCharacter);
+  }
+  Ok(())
+}
+
+fn check_address(arg: &str) -> Result<(), AssembleError> {
+  // Symbolic addresses are checked when they are resolved in the second pass
+  if is_number(arg) && !is_strict_number(arg) {
+    return Err(AssembleError::BadAddressCharacter);
+  }
+  Ok(())
+}
+
+fn check_label(label: &str) -> Result<(), AssembleError> {
+  if label.len() > MAX_LABEL_LENGTH {
+    return Err(AssembleError::LabelTooLong);
+  }
+  if !label.starts_with(|c: char| c.is_ascii_alphabetic()) {
+    return Err(AssembleError::LabelStartsWithNonLetter);
+  }
+  if !label.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(AssembleError::LabelHasBadCharacter);
+  }
+  Ok(())
+}
+
+fn encode_line(
+  line: &Line,
+  address: usize,
+  labels: &[(String, usize)],
+) -> Result<i32, AssembleError> {
+  let opcode: i32 = match line.opcode {
+    "add" => 0,
+    "nand" => 1,
+    "lw" => 2,
+    "sw" => 3,
+    "beq" => 4,
+    "jalr" => 5,
+    "halt" => 6,
+    "noop" => 7,
+    ".fill" => {
+      return if is_number(line.arg0) {
+        Ok(atoi(line.arg0) as i32)
+      } else {
+        Ok(translate_symbol(labels, line.arg0)? as i32)
+      };
+    }
+    _ => unreachable!("opcodes are validated in the first pass"),
+  };
+
+  let reg_a = atoi(line.arg0) as i32;
+  let reg_b = atoi(line.arg1) as i32;
+  let word = match line.opcode {
+    "add" | "nand" => (opcode << 22) | (reg_a << 19) | (reg_b << 16) | atoi(line.arg2) as i32,
+    "jalr" => (opcode << 22) | (reg_a << 19) | (reg_b << 16),
+    "halt" | "noop" => opcode << 22,
+    _ => {
+      // lw, sw and beq, a symbolic beq offset is relative to the next instruction
+      let offset = if is_number(line.arg2) {
+        atoi(line.arg2)
+      } else {
+        let label_address = translate_symbol(labels, line.arg2)? as i64;
+        if line.opcode == "beq" {
+          label_address - address as i64 - 1
+        } else {
+          label_address
+        }
+      };
+
+      if !(-32768..=32767).contains(&offset) {
+        return Err(AssembleError::OffsetOutOfRange { offset });
+      }
+
+      // Truncate the offset field in case it's negative
+      (opcode << 22) | (reg_a << 19) | (reg_b << 16) | (offset as i32 & 0xFFFF)
+    }
+  };
+
+  Ok(word)
+}
+
+fn translate_symbol(labels: &[(String, usize)], symbol: &str) -> Result<usize, AssembleError> {
+  labels
+    .iter()
+    .find(|(name, _)| name == symbol)
+    .map(|(_, address)| *address)
+    .ok_or_else(|| AssembleError::MissingLabel {
+      label: symbol.to_string(),
+    })
+}
+
+/// Returns how many leading bytes of `arg` form a number the way `sscanf("%d")` reads one
+fn number_length(arg: &str) -> Option<usize> {
+  let bytes = arg.as_bytes();
+  let sign_length = if bytes.first().is_some_and(|b| *b == b'-' || *b == b'+') {
+    1
+  } else {
+    0
+  };
+  let digit_count = bytes[sign_length..]
+    .iter()
+    .take_while(|b| b.is_ascii_digit())
+    .count();
+
+  if digit_count == 0 {
+    None
+  } else {
+    Some(sign_length + digit_count)
+  }
+}
+
+/// Equivalent of the C assembler's `isNumber`, true if the argument starts with a number
+fn is_number(arg: &str) -> bool {
+  number_length(arg).is_some()
+}
+
+/// True if the argument is a number with nothing trailing it
+fn is_strict_number(arg: &str) -> bool {
+  number_length(arg) == Some(arg.len())
+}
+
+/// Equivalent of C's `atoi`, parses the leading number of the argument (0 if there is none)
+fn atoi(arg: &str) -> i64 {
+  match number_length(arg) {
+    Some(length) => arg[..length]
+      .parse::<i64>()
+      .unwrap_or(if arg.starts_with('-') {
+        i64::MIN
+      } else {
+        i64::MAX
+      }),
+    None => 0,
+  }
+}
@@ -1,20 +1,18 @@
-use crate::utils;
-
-pub enum Action {
-  CacheToProcessor,
-  ProcessorToCache,
-  MemoryToCache,
-  CacheToMemory,
-  CacheToNowhere,
-}
+use crate::error::SimError;
+use crate::memory::MemoryLevel;
+use crate::replacement::{new_policy, Lru, ReplacementKind, ReplacementPolicy};
+use crate::utils::{self, PROCESSOR};
+use crate::NUM_MEMORY;
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::str::FromStr;
 
-#[derive(Clone)]
-struct Block {
+/// A block of a cache set, the tag and data are only meaningful while the block is valid
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
   valid: bool,
   dirty: bool,
   tag: usize,
-  // Technical detail for calculating LRU
-  instructions_since_use: usize,
   // Not strictly necessary, but avoids calculations later down the line
   starting_address: usize,
   data: Vec<i32>,
@@ -23,14 +21,312 @@ struct Block {
 #[derive(Clone)]
 struct Set(Vec<Block>);
 
+/// What a cache access is being made for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+  InstructionFetch,
+  Load,
+  Store,
+}
+
+/// When stores that hit in the cache reach memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum WritePolicy {
+  /// Only when the dirty block is evicted (or the machine halts)
+  Back,
+  /// Immediately, the word is written to the cache and memory (or the write buffer)
+  Through,
+}
+
+/// What a store that misses in the cache does
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum WriteMissPolicy {
+  /// Bring the block into the cache first, then write to it
+  Allocate,
+  /// Write the word straight to memory (or the write buffer) without caching it
+  NoAllocate,
+}
+
+/// Everything needed to build a cache
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+  pub block_size_in_words: usize,
+  pub number_of_sets: usize,
+  pub blocks_per_set: usize,
+  pub replacement: ReplacementKind,
+  /// Seed for random replacement
+  pub seed: u64,
+  pub write_policy: WritePolicy,
+  pub write_miss_policy: WriteMissPolicy,
+  /// Number of words the write buffer holds, 0 for no write buffer (write-through only)
+  pub write_buffer_size: usize,
+  /// Cycles taken by every access to the cache, hit or miss (the time to check the tags)
+  pub hit_latency: usize,
+}
+
+/// Counters for everything the cache has done since it was created
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  pub instruction_hits: usize,
+  pub instruction_misses: usize,
+  pub load_hits: usize,
+  pub load_misses: usize,
+  pub store_hits: usize,
+  pub store_misses: usize,
+  /// Valid blocks replaced that didn't need to be written back
+  pub clean_evictions: usize,
+  /// Valid blocks replaced that were written back to memory first
+  pub dirty_evictions: usize,
+  /// Dirty blocks written back when the machine halted
+  pub halt_writebacks: usize,
+  pub words_from_memory: usize,
+  pub words_to_memory: usize,
+  /// Stored words sent on past the cache by write-through or no-write-allocate
+  pub write_throughs: usize,
+  /// Stores that overwrote a word already waiting in the write buffer
+  pub write_buffer_coalesced: usize,
+  /// Words moved from the write buffer to memory
+  pub write_buffer_drains: usize,
+  /// Cycles spent on accesses to the cache, the hit latency for each one
+  pub access_cycles: usize,
+}
+
+/// What a cache looked like before the accesses made since `Cache::start_undo`, everything
+/// needed to put it back with `Cache::undo`
+pub struct CacheUndo {
+  // Every set the accesses touched, with its blocks and replacement bookkeeping from before
+  sets: Vec<(usize, Set, Vec<u64>)>,
+  write_buffer: VecDeque<(usize, i32)>,
+  stats: CacheStats,
+}
+
+/// Everything about a cache that changes as it is used, so it can be saved with `Cache::save`
+/// and put back later (in a cache with the same geometry and policies) with `Cache::restore`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheSnapshot {
+  /// The blocks of each set, along with the replacement policy's bookkeeping for the set
+  pub sets: Vec<(Vec<Block>, Vec<u64>)>,
+  /// Pending (address, value) writes, oldest first
+  pub write_buffer: Vec<(usize, i32)>,
+  pub stats: CacheStats,
+}
+
 pub struct Cache {
+  name: String,
   sets: Vec<Set>,
+  policy: Box<dyn ReplacementPolicy>,
+  write_policy: WritePolicy,
+  write_miss_policy: WriteMissPolicy,
+  // Pending (address, value) writes, oldest first
+  write_buffer: VecDeque<(usize, i32)>,
+  write_buffer_size: usize,
+  write_buffer_name: String,
+  hit_latency: usize,
+  // Where changes are remembered while they can be undone
+  undo: Option<CacheUndo>,
+  stats: CacheStats,
   offset_bit_count: usize,
   set_bit_count: usize,
   block_size: usize,
   set_count: usize,
 }
 
+impl CacheConfig {
+  /// A write-back, write-allocate cache using LRU replacement, taking 1 cycle per access
+  pub fn new(
+    block_size_in_words: usize,
+    number_of_sets: usize,
+    blocks_per_set: usize,
+  ) -> CacheConfig {
+    CacheConfig {
+      block_size_in_words,
+      number_of_sets,
+      blocks_per_set,
+      replacement: ReplacementKind::Lru,
+      seed: 1,
+      write_policy: WritePolicy::Back,
+      write_miss_policy: WriteMissPolicy::Allocate,
+      write_buffer_size: 0,
+      hit_latency: 1,
+    }
+  }
+
+  /// Checks the geometry and policies make sense together: every size must be a power of 2,
+  /// blocks must fit inside memory, and the whole cache can't be larger than memory
+  pub fn validate(&self) -> Result<(), SimError> {
+    let geometry = format!(
+      "{}.{}.{}",
+      self.block_size_in_words, self.number_of_sets, self.blocks_per_set
+    );
+    let sizes = [
+      ("block size", self.block_size_in_words),
+      ("number of sets", self.number_of_sets),
+      ("number of blocks per set", self.blocks_per_set),
+    ];
+    for (name, size) in sizes {
+      if size == 0 {
+        return Err(SimError::InvalidCache(format!(
+          "The {} of cache {} can't be 0",
+          name, geometry
+        )));
+      }
+      if !utils::is_power_of_two(size) {
+        return Err(SimError::InvalidCache(format!(
+          "The {} of cache {} must be a power of 2, not {}",
+          name, geometry, size
+        )));
+      }
+    }
+
+    // Blocks start at multiples of their size, so a power of 2 size up to the size of memory
+    // (itself a power of 2) always ends inside memory
+    if self.block_size_in_words > NUM_MEMORY {
+      return Err(SimError::InvalidCache(format!(
+        "The {} word blocks of cache {} run past the end of the {} words of memory",
+        self.block_size_in_words, geometry, NUM_MEMORY
+      )));
+    }
+    let words = self
+      .block_size_in_words
+      .checked_mul(self.number_of_sets)
+      .and_then(|words| words.checked_mul(self.blocks_per_set));
+    if words.is_none_or(|words| words > NUM_MEMORY) {
+      return Err(SimError::InvalidCache(format!(
+        "Cache {} holds more words than the {} words of memory",
+        geometry, NUM_MEMORY
+      )));
+    }
+
+    if self.write_buffer_size > 0 && self.write_policy != WritePolicy::Through {
+      return Err(SimError::InvalidCache(
+        "A write buffer can only be used with the write-through policy".to_string(),
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Parses a cache level from the command line: the geometry as `B.S.A` (block size in words,
+/// number of sets, blocks per set), optionally followed by comma separated policy options,
+/// e.g. `4.8.2,replacement=fifo,write=through,write-buffer=4,hit-latency=4`
+impl FromStr for CacheConfig {
+  type Err = String;
+
+  fn from_str(spec: &str) -> Result<Self, Self::Err> {
+    let mut parts = spec.split(',');
+    let geometry: Vec<&str> = parts.next().unwrap_or("").split('.').collect();
+    let [block_size, sets, blocks_per_set] = geometry[..] else {
+      return Err(format!(
+        "expected B.S.A for the cache geometry, found `{}`",
+        spec
+      ));
+    };
+    let number = |field: &str| {
+      field
+        .parse::<usize>()
+        .map_err(|_| format!("`{}` is not a valid number", field))
+    };
+    let mut config = CacheConfig::new(number(block_size)?, number(sets)?, number(blocks_per_set)?);
+
+    for option in parts {
+      let Some((key, value)) = option.split_once('=') else {
+        return Err(format!("expected key=value, found `{}`", option));
+      };
+      match key {
+        "replacement" => config.replacement = ReplacementKind::from_str(value, true)?,
+        "seed" => {
+          config.seed = value
+            .parse()
+            .map_err(|_| format!("`{}` is not a valid seed", value))?
+        }
+        "write" => config.write_policy = WritePolicy::from_str(value, true)?,
+        "write-miss" => config.write_miss_policy = WriteMissPolicy::from_str(value, true)?,
+        "write-buffer" => config.write_buffer_size = number(value)?,
+        "hit-latency" => config.hit_latency = number(value)?,
+        _ => return Err(format!("unknown cache option `{}`", key)),
+      }
+    }
+    Ok(config)
+  }
+}
+
+impl CacheStats {
+  fn record_access(&mut self, kind: AccessKind, hit: bool) {
+    let counter = match (kind, hit) {
+      (AccessKind::InstructionFetch, true) => &mut self.instruction_hits,
+      (AccessKind::InstructionFetch, false) => &mut self.instruction_misses,
+      (AccessKind::Load, true) => &mut self.load_hits,
+      (AccessKind::Load, false) => &mut self.load_misses,
+      (AccessKind::Store, true) => &mut self.store_hits,
+      (AccessKind::Store, false) => &mut self.store_misses,
+    };
+    *counter += 1;
+  }
+
+  /// Instruction fetches and loads
+  pub fn reads(&self) -> usize {
+    self.instruction_hits + self.instruction_misses + self.load_hits + self.load_misses
+  }
+
+  /// Stores
+  pub fn writes(&self) -> usize {
+    self.store_hits + self.store_misses
+  }
+
+  pub fn hits(&self) -> usize {
+    self.instruction_hits + self.load_hits + self.store_hits
+  }
+
+  pub fn misses(&self) -> usize {
+    self.instruction_misses + self.load_misses + self.store_misses
+  }
+
+  /// Hits as a fraction of all accesses, 0 if the cache was never accessed
+  pub fn hit_rate(&self) -> f64 {
+    let accesses = self.hits() + self.misses();
+    if accesses == 0 {
+      return 0.0;
+    }
+    self.hits() as f64 / accesses as f64
+  }
+
+  pub fn to_json(&self) -> String {
+    format!(
+      concat!(
+        "{{\"reads\":{},\"writes\":{},\"hits\":{},\"misses\":{},\"hit_rate\":{:.4},",
+        "\"instruction_hits\":{},\"instruction_misses\":{},",
+        "\"data_hits\":{},\"data_misses\":{},",
+        "\"load_hits\":{},\"load_misses\":{},\"store_hits\":{},\"store_misses\":{},",
+        "\"clean_evictions\":{},\"dirty_evictions\":{},\"halt_writebacks\":{},",
+        "\"words_from_memory\":{},\"words_to_memory\":{},\"write_throughs\":{},",
+        "\"write_buffer_coalesced\":{},\"write_buffer_drains\":{},\"access_cycles\":{}}}"
+      ),
+      self.reads(),
+      self.writes(),
+      self.hits(),
+      self.misses(),
+      self.hit_rate(),
+      self.instruction_hits,
+      self.instruction_misses,
+      self.load_hits + self.store_hits,
+      self.load_misses + self.store_misses,
+      self.load_hits,
+      self.load_misses,
+      self.store_hits,
+      self.store_misses,
+      self.clean_evictions,
+      self.dirty_evictions,
+      self.halt_writebacks,
+      self.words_from_memory,
+      self.words_to_memory,
+      self.write_throughs,
+      self.write_buffer_coalesced,
+      self.write_buffer_drains,
+      self.access_cycles,
+    )
+  }
+}
+
 impl Block {
   fn new(block_size_in_words: usize) -> Block {
     Block {
@@ -38,56 +334,89 @@ impl Block {
       dirty: false,
       // Tag 0 by default, will not matter since valid bit shows its unused
       tag: 0,
-      instructions_since_use: 0,
       starting_address: 0,
       data: vec![0; block_size_in_words],
     }
   }
-}
 
-impl Set {
-  fn new(block_size_in_words: usize, blocks_per_set: usize) -> Set {
-    Set(vec![Block::new(block_size_in_words); blocks_per_set])
+  /// A block holding `data`, for putting a cache back from a `CacheSnapshot`
+  pub fn with_contents(
+    valid: bool,
+    dirty: bool,
+    tag: usize,
+    starting_address: usize,
+    data: Vec<i32>,
+  ) -> Block {
+    Block {
+      valid,
+      dirty,
+      tag,
+      starting_address,
+      data,
+    }
   }
 
-  /// Calculates the block in the set that is the least recently used and returns it's index
-  fn least_recently_used(&self) -> usize {
-    let mut lru_idx: usize = 0;
-    let mut lru_instruction_count: Option<usize> = None;
-    for (i, block) in self.0.iter().enumerate() {
-      // Invalid blocks will always get first priority
-      if !block.valid {
-        return i;
-      }
+  pub fn is_valid(&self) -> bool {
+    self.valid
+  }
 
-      if lru_instruction_count.is_none() {
-        lru_idx = i;
-        lru_instruction_count = Some(block.instructions_since_use);
-      } else {
-        let lru_count = lru_instruction_count.unwrap();
+  pub fn is_dirty(&self) -> bool {
+    self.dirty
+  }
 
-        if block.instructions_since_use > lru_count {
-          lru_instruction_count = Some(block.instructions_since_use);
-          lru_idx = i;
-        }
-      }
-    }
-    return lru_idx;
+  pub fn tag(&self) -> usize {
+    self.tag
   }
 
-  // Increments the blocks' instructions_since_use value for every block
-  // except the block index/tag used
-  fn use_block(&mut self, newest_index: usize) {
-    for (i, block) in self.0.iter_mut().enumerate() {
-      if i != newest_index {
-        block.instructions_since_use += 1;
-      }
-    }
+  /// Address of the first word in the block
+  pub fn starting_address(&self) -> usize {
+    self.starting_address
+  }
+
+  pub fn data(&self) -> &[i32] {
+    &self.data
+  }
+}
+
+impl Set {
+  fn new(block_size_in_words: usize, blocks_per_set: usize) -> Set {
+    Set(vec![Block::new(block_size_in_words); blocks_per_set])
   }
 }
 
 impl Cache {
+  /// Creates a cache using LRU replacement
   pub fn new(block_size_in_words: usize, number_of_sets: usize, blocks_per_set: usize) -> Cache {
+    let policy = Box::new(Lru::new(number_of_sets, blocks_per_set));
+    Cache::with_policy(block_size_in_words, number_of_sets, blocks_per_set, policy)
+  }
+
+  pub fn from_config(config: &CacheConfig) -> Cache {
+    let policy = new_policy(
+      config.replacement,
+      config.number_of_sets,
+      config.blocks_per_set,
+      config.seed,
+    );
+    let mut cache = Cache::with_policy(
+      config.block_size_in_words,
+      config.number_of_sets,
+      config.blocks_per_set,
+      policy,
+    );
+    cache.write_policy = config.write_policy;
+    cache.write_miss_policy = config.write_miss_policy;
+    cache.write_buffer_size = config.write_buffer_size;
+    cache.hit_latency = config.hit_latency;
+    cache
+  }
+
+  pub fn with_policy(
+    block_size_in_words: usize,
+    number_of_sets: usize,
+    blocks_per_set: usize,
+    policy: Box<dyn ReplacementPolicy>,
+  ) -> Cache {
     let offset_bit_count = if block_size_in_words == 1 {
       0
     } else {
@@ -101,7 +430,17 @@ impl Cache {
     };
 
     Cache {
+      name: "cache".to_string(),
       sets: vec![Set::new(block_size_in_words, blocks_per_set); number_of_sets],
+      policy,
+      write_policy: WritePolicy::Back,
+      write_miss_policy: WriteMissPolicy::Allocate,
+      write_buffer: VecDeque::new(),
+      write_buffer_size: 0,
+      write_buffer_name: "write buffer".to_string(),
+      hit_latency: 1,
+      undo: None,
+      stats: CacheStats::default(),
       offset_bit_count,
       set_bit_count,
       block_size: block_size_in_words,
@@ -109,198 +448,520 @@ impl Cache {
     }
   }
 
+  /// Name used for this cache in transfer messages
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Renames the cache, e.g. "L1 cache" when it's one level of a hierarchy
+  pub fn set_name(&mut self, name: &str) {
+    self.name = name.to_string();
+    self.write_buffer_name = format!("{} write buffer", name);
+  }
+
   /// Used for getting a word from the cache (if not in cache, will add from memory first)
-  pub fn get_value(&mut self, address: usize, memory: &mut [i32]) -> i32 {
-    // self.print_cache();
-    let tag = self.get_tag(address);
-    let set_index = self.get_set_index(address);
+  pub fn get_value(
+    &mut self,
+    address: usize,
+    memory: &mut dyn MemoryLevel,
+    kind: AccessKind,
+  ) -> i32 {
+    self.stats.access_cycles += self.hit_latency;
+    let (set_index, block_index) = self.find_or_load_block(address, memory, kind);
     let block_offset = self.get_block_offset(address);
-    let (small_bound, large_bound) = self.get_block_bounds(address);
+    let data = self.sets[set_index].0[block_index].data[block_offset];
 
-    // println!("address:      {} -- {:b}", address, address);
-    // println!("tag:          {} -- {:b}", tag, tag);
-    // println!("set_index:    {} -- {:b}", set_index, set_index);
-    // println!("block_offset: {} -- {:b}", block_offset, block_offset);
-
-    let set = self.sets.get_mut(set_index).expect("Incorrect set index");
-    for (i, block) in set.0.iter_mut().enumerate() {
-      if block.tag == tag && block.valid {
-        let data = *block
-          .data
-          .get(block_offset)
-          .expect("Incorrect block offset");
-
-        // Edit instructions_since_use for all blocks for calculating LRU
-        block.instructions_since_use = 0;
-        set.use_block(i);
-
-        utils::print_action(address, 1, Action::CacheToProcessor);
-        return data;
+    utils::print_action(address, 1, &self.name, PROCESSOR);
+    data
+  }
+
+  /// Used for storing a word in the cache. If the word's block is not in the cache it is added
+  /// first, unless the cache is no-write-allocate in which case the word skips the cache.
+  pub fn set_value(&mut self, address: usize, memory: &mut dyn MemoryLevel, value: i32) {
+    self.stats.access_cycles += self.hit_latency;
+    if !self.store(address, &[value], PROCESSOR, memory) {
+      return;
+    }
+    utils::print_action(address, 1, PROCESSOR, &self.name);
+
+    if self.write_policy == WritePolicy::Through {
+      let name = self.name.clone();
+      self.write_past_cache(address, &[value], &name, memory);
+    }
+  }
+
+  /// Reads the words starting at `address` into `data` for the level above this cache, bringing
+  /// each block they fall in into the cache first
+  pub fn read_block(
+    &mut self,
+    address: usize,
+    data: &mut [i32],
+    kind: AccessKind,
+    memory: &mut dyn MemoryLevel,
+  ) {
+    self.stats.access_cycles += self.hit_latency;
+    let mut offset = 0;
+    while offset < data.len() {
+      let chunk_address = address + offset;
+      let end = self.chunk_end(chunk_address, address, data.len());
+      let (set_index, block_index) = self.find_or_load_block(chunk_address, memory, kind);
+      let block_offset = self.get_block_offset(chunk_address);
+      let block = &self.sets[set_index].0[block_index];
+      data[offset..end].copy_from_slice(&block.data[block_offset..block_offset + end - offset]);
+      offset = end;
+    }
+  }
+
+  /// Writes `data` starting at `address` from the level above this cache, following this
+  /// cache's write policies for each block the words fall in
+  pub fn write_block(&mut self, address: usize, data: &[i32], memory: &mut dyn MemoryLevel) {
+    self.stats.access_cycles += self.hit_latency;
+    let name = self.name.clone();
+    let mut offset = 0;
+    while offset < data.len() {
+      let chunk_address = address + offset;
+      let end = self.chunk_end(chunk_address, address, data.len());
+      let chunk = &data[offset..end];
+      if self.store(chunk_address, chunk, &name, memory)
+        && self.write_policy == WritePolicy::Through
+      {
+        self.write_past_cache(chunk_address, chunk, &name, memory);
       }
+      offset = end;
     }
+  }
 
-    // Since the address is not in the cache, we have to go to memory and add it to the cache
-    let block_data = memory[small_bound..(large_bound + 1)].to_owned().to_vec();
-    let lru_idx = set.least_recently_used();
-    let block_reference = set.0.get_mut(lru_idx).expect("Incorrect LRU index");
-    let old_block = std::mem::replace(
-      block_reference,
-      Block {
-        valid: true,
-        dirty: false,
-        tag,
-        instructions_since_use: 0,
-        starting_address: small_bound,
-        data: block_data,
-      },
-    );
+  /// Starts remembering how accesses change the cache so they can be undone with `undo`
+  pub fn start_undo(&mut self) {
+    self.undo = Some(CacheUndo {
+      sets: Vec::new(),
+      write_buffer: self.write_buffer.clone(),
+      stats: self.stats.clone(),
+    });
+  }
 
-    // Increment all other blocks instructions_since_use for calculating the LRU
-    set.use_block(lru_idx);
+  /// Stops remembering changes, returning what's needed to undo everything since `start_undo`
+  pub fn finish_undo(&mut self) -> Option<CacheUndo> {
+    self.undo.take()
+  }
 
-    // Now that we have replaced the old block in the cache, we have to determine 2 things:
-    // - if that block is valid
-    // - if the block was valid, we also have to check if it was dirty or not to write back to memory
-    if old_block.valid {
-      if old_block.dirty {
-        let memory_block = &mut memory[small_bound..(large_bound + 1)];
-        assert!(
-          memory_block.len() == old_block.data.len(),
-          "Cache block length does not match memory block length\ncache: {}\nmemory: {}",
-          old_block.data.len(),
-          memory_block.len(),
-        );
-        memory_block.copy_from_slice(&old_block.data);
-        utils::print_action(
-          old_block.starting_address,
-          self.block_size,
-          Action::CacheToMemory,
-        );
-      } else {
-        // old_block is already out of the cache, the memory for it will be deallocated at the end of the function
-        utils::print_action(
-          old_block.starting_address,
-          self.block_size,
-          Action::CacheToNowhere,
-        );
+  /// Puts the cache back the way it was when `start_undo` was called, including the
+  /// replacement policy's bookkeeping and the statistics
+  pub fn undo(&mut self, undo: CacheUndo) {
+    // Newest first, so state shared between sets ends up at its oldest value
+    for (set_index, set, policy_state) in undo.sets.into_iter().rev() {
+      self.sets[set_index] = set;
+      self.policy.restore(set_index, &policy_state);
+    }
+    self.write_buffer = undo.write_buffer;
+    self.stats = undo.stats;
+  }
+
+  /// Copies out the blocks, replacement policy bookkeeping, write buffer and statistics
+  pub fn save(&self) -> CacheSnapshot {
+    CacheSnapshot {
+      sets: (0..self.set_count)
+        .map(|set_index| (self.sets[set_index].0.clone(), self.policy.save(set_index)))
+        .collect(),
+      write_buffer: self.write_buffer.iter().copied().collect(),
+      stats: self.stats.clone(),
+    }
+  }
+
+  /// Checks a `save` came from a cache with the same geometry and replacement policy, and holds
+  /// bookkeeping the policy could be left with, so it can be restored into this one
+  pub fn check_snapshot(&self, snapshot: &CacheSnapshot) -> Result<(), SimError> {
+    let matches = snapshot.sets.len() == self.set_count
+      && snapshot
+        .sets
+        .iter()
+        .enumerate()
+        .all(|(set_index, (blocks, policy_state))| {
+          blocks.len() == self.associativity()
+            && blocks
+              .iter()
+              .all(|block| block.data.len() == self.block_size)
+            && policy_state.len() == self.policy.save(set_index).len()
+        });
+    if !matches {
+      return Err(SimError::InvalidCache(format!(
+        "The saved {} doesn't match the {}.{}.{} cache it is being restored into (B.S.A and \
+         the replacement policy must be the same)",
+        self.name,
+        self.block_size,
+        self.set_count,
+        self.associativity()
+      )));
+    }
+    let unusable = snapshot
+      .sets
+      .iter()
+      .enumerate()
+      .find(|(set_index, (_, policy_state))| !self.policy.accepts(*set_index, policy_state));
+    if let Some((set_index, _)) = unusable {
+      return Err(SimError::InvalidCache(format!(
+        "The saved {} has replacement policy state for set {} that its policy can't be in",
+        self.name, set_index
+      )));
+    }
+    Ok(())
+  }
+
+  /// Puts back everything from a `save`, see `check_snapshot`. Anything being remembered for
+  /// `undo` is thrown away.
+  pub fn restore(&mut self, snapshot: CacheSnapshot) -> Result<(), SimError> {
+    self.check_snapshot(&snapshot)?;
+    for (set_index, (blocks, policy_state)) in snapshot.sets.into_iter().enumerate() {
+      self.sets[set_index] = Set(blocks);
+      self.policy.restore(set_index, &policy_state);
+    }
+    self.write_buffer = snapshot.write_buffer.into_iter().collect();
+    self.stats = snapshot.stats;
+    self.undo = None;
+    Ok(())
+  }
+
+  /// Saves a set before it's changed for the first time while changes are being remembered
+  fn remember_set(&mut self, set_index: usize) {
+    let Some(undo) = self.undo.as_mut() else {
+      return;
+    };
+    if undo
+      .sets
+      .iter()
+      .all(|(saved_index, _, _)| *saved_index != set_index)
+    {
+      undo.sets.push((
+        set_index,
+        self.sets[set_index].clone(),
+        self.policy.save(set_index),
+      ));
+    }
+  }
+
+  /// Where the words from `chunk_address` to the end of its block (or the end of the `length`
+  /// words starting at `address`) end, as an offset from `address`
+  fn chunk_end(&self, chunk_address: usize, address: usize, length: usize) -> usize {
+    let (_, large_bound) = self.get_block_bounds(chunk_address);
+    length.min(large_bound + 1 - address)
+  }
+
+  /// Writes `values` (which all fall in one block) starting at `address`. Returns false if the
+  /// block wasn't cached and the cache is no-write-allocate, in which case the words were sent
+  /// on from `source` without going through the cache.
+  fn store(
+    &mut self,
+    address: usize,
+    values: &[i32],
+    source: &str,
+    memory: &mut dyn MemoryLevel,
+  ) -> bool {
+    self.remember_set(self.get_set_index(address));
+    let (set_index, block_index) = match self.find_block(address) {
+      Some((set_index, block_index)) => {
+        self.policy.touch(set_index, block_index);
+        self.stats.record_access(AccessKind::Store, true);
+        (set_index, block_index)
+      }
+      None => {
+        self.stats.record_access(AccessKind::Store, false);
+        match self.write_miss_policy {
+          WriteMissPolicy::Allocate => self.load_block(address, AccessKind::Store, memory),
+          WriteMissPolicy::NoAllocate => {
+            // The words go around the cache
+            self.write_past_cache(address, values, source, memory);
+            return false;
+          }
+        }
+      }
+    };
+
+    let block_offset = self.get_block_offset(address);
+    let block = &mut self.sets[set_index].0[block_index];
+    block.data[block_offset..block_offset + values.len()].copy_from_slice(values);
+    // Write-through blocks always match the level below, so they are never dirty
+    if self.write_policy == WritePolicy::Back {
+      block.dirty = true;
+    }
+    true
+  }
+
+  /// Sends stored words on to the level below for write-through and no-write-allocate, going
+  /// through the write buffer if there is one. `source` is where the words are coming from.
+  fn write_past_cache(
+    &mut self,
+    address: usize,
+    values: &[i32],
+    source: &str,
+    memory: &mut dyn MemoryLevel,
+  ) {
+    self.stats.write_throughs += values.len();
+    if self.write_buffer_size == 0 {
+      memory.write_block(address, values);
+      self.stats.words_to_memory += values.len();
+      utils::print_action(address, values.len(), source, memory.name());
+      return;
+    }
+
+    for (idx, value) in values.iter().enumerate() {
+      let word_address = address + idx;
+      // A write to an address already waiting in the buffer just replaces the old value
+      if let Some(entry) = self
+        .write_buffer
+        .iter_mut()
+        .find(|entry| entry.0 == word_address)
+      {
+        entry.1 = *value;
+        self.stats.write_buffer_coalesced += 1;
+        continue;
+      }
+
+      if self.write_buffer.len() == self.write_buffer_size {
+        self.drain_oldest_write(memory);
+      }
+      self.write_buffer.push_back((word_address, *value));
+    }
+    utils::print_action(address, values.len(), source, &self.write_buffer_name);
+  }
+
+  fn drain_oldest_write(&mut self, memory: &mut dyn MemoryLevel) {
+    if let Some((address, value)) = self.write_buffer.pop_front() {
+      memory.write_block(address, &[value]);
+      self.stats.write_buffer_drains += 1;
+      self.stats.words_to_memory += 1;
+      utils::print_action(address, 1, &self.write_buffer_name, memory.name());
+    }
+  }
+
+  /// Drains every buffered write in `small_bound..=large_bound` so the level below is up to date
+  /// before that range is read. Writes in the range are drained in the order they were buffered.
+  fn drain_writes_in_range(
+    &mut self,
+    small_bound: usize,
+    large_bound: usize,
+    memory: &mut dyn MemoryLevel,
+  ) {
+    while let Some(idx) = self
+      .write_buffer
+      .iter()
+      .position(|(address, _)| (small_bound..=large_bound).contains(address))
+    {
+      // Everything ahead of the write in the buffer has to reach memory first
+      for _ in 0..=idx {
+        self.drain_oldest_write(memory);
       }
     }
+  }
 
-    // Block is invalid so the new data is just added to the cache
-    utils::print_action(small_bound, self.block_size, Action::MemoryToCache);
-    // Since the block is now in the cache, rather than reimplementing all of the logic, we can just
-    // call the function recursively to send the cached result to the processor
-    return self.get_value(address, memory);
+  /// Finds the block holding `address` without counting it as a use
+  fn find_block(&self, address: usize) -> Option<(usize, usize)> {
+    let tag = self.get_tag(address);
+    let set_index = self.get_set_index(address);
+    self.sets[set_index]
+      .0
+      .iter()
+      .position(|block| block.valid && block.tag == tag)
+      .map(|block_index| (set_index, block_index))
   }
 
-  /// Used for storing a word in the cache (if the word's block is not in the cache, will add it first)
-  pub fn set_value(&mut self, address: usize, memory: &mut [i32], value: i32) {
+  /// Finds the block holding `address`, adding it to the cache from memory first if it's not
+  /// already there. Returns the index of the set and the index of the block within that set.
+  fn find_or_load_block(
+    &mut self,
+    address: usize,
+    memory: &mut dyn MemoryLevel,
+    kind: AccessKind,
+  ) -> (usize, usize) {
     // self.print_cache();
+    self.remember_set(self.get_set_index(address));
+    if let Some((set_index, block_index)) = self.find_block(address) {
+      self.policy.touch(set_index, block_index);
+      self.stats.record_access(kind, true);
+      return (set_index, block_index);
+    }
+    self.stats.record_access(kind, false);
+    self.load_block(address, kind, memory)
+  }
+
+  /// Brings the block holding `address` into the cache from the level below, evicting a block
+  /// from its set if needed. Returns the index of the set and the index of the block within
+  /// that set.
+  fn load_block(
+    &mut self,
+    address: usize,
+    kind: AccessKind,
+    memory: &mut dyn MemoryLevel,
+  ) -> (usize, usize) {
     let tag = self.get_tag(address);
     let set_index = self.get_set_index(address);
-    let block_offset = self.get_block_offset(address);
     let (small_bound, large_bound) = self.get_block_bounds(address);
 
     // println!("address:      {} -- {:b}", address, address);
     // println!("tag:          {} -- {:b}", tag, tag);
     // println!("set_index:    {} -- {:b}", set_index, set_index);
-    // println!("block_offset: {} -- {:b}", block_offset, block_offset);
-
-    let set = self.sets.get_mut(set_index).expect("Incorrect set index");
-    for (i, block) in set.0.iter_mut().enumerate() {
-      if block.tag == tag && block.valid {
-        let row = block
-          .data
-          .get_mut(block_offset)
-          .expect("Incorrect block offset");
-        *row = value;
-        block.dirty = true;
-
-        // Edit instructions_since_use for all blocks for calculating LRU
-        block.instructions_since_use = 0;
-        set.use_block(i);
-
-        utils::print_action(address, 1, Action::ProcessorToCache);
-        return;
-      }
-    }
 
-    // If we are here, it means that the address's block was not in the cache, so we must add it
-    let block_data = memory[small_bound..(large_bound + 1)].to_owned().to_vec();
-    let lru_idx = set.least_recently_used();
-    let block_reference = set.0.get_mut(lru_idx).expect("Incorrect LRU index");
+    // Memory might be behind the write buffer for this block
+    self.drain_writes_in_range(small_bound, large_bound, memory);
+
+    // Invalid blocks will always get first priority, otherwise the replacement policy decides
+    let victim_idx = match self.sets[set_index].0.iter().position(|block| !block.valid) {
+      Some(invalid_idx) => invalid_idx,
+      None => self.policy.victim(set_index),
+    };
     let old_block = std::mem::replace(
-      block_reference,
+      &mut self.sets[set_index].0[victim_idx],
       Block {
         valid: true,
         dirty: false,
         tag,
-        instructions_since_use: 0,
         starting_address: small_bound,
-        data: block_data,
+        data: vec![0; self.block_size],
       },
     );
 
-    // Increment all other blocks instructions_since_use for calculating the LRU
-    set.use_block(lru_idx);
+    self.policy.fill(set_index, victim_idx);
 
     // Now that we have replaced the old block in the cache, we have to determine 2 things:
     // - if that block is valid
     // - if the block was valid, we also have to check if it was dirty or not to write back to memory
     if old_block.valid {
       if old_block.dirty {
-        let memory_block = &mut memory[small_bound..(large_bound + 1)];
-        assert!(
-          memory_block.len() == old_block.data.len(),
-          "Cache block length does not match memory block length\ncache: {}\nmemory: {}",
-          old_block.data.len(),
-          memory_block.len(),
-        );
-        memory_block.copy_from_slice(&old_block.data);
+        // The victim is written back to its own range, not the range of the incoming block
+        memory.write_block(old_block.starting_address, &old_block.data);
+        self.stats.dirty_evictions += 1;
+        self.stats.words_to_memory += self.block_size;
         utils::print_action(
           old_block.starting_address,
           self.block_size,
-          Action::CacheToMemory,
+          &self.name,
+          memory.name(),
         );
       } else {
         // old_block is already out of the cache, the memory for it will be deallocated at the end of the function
+        self.stats.clean_evictions += 1;
         utils::print_action(
           old_block.starting_address,
           self.block_size,
-          Action::CacheToNowhere,
+          &self.name,
+          utils::NOWHERE,
         );
       }
     }
 
-    // Block is invalid so the new data is just added to the cache
-    utils::print_action(small_bound, self.block_size, Action::MemoryToCache);
+    // Since the address is not in the cache, we have to go to the level below and add it to the
+    // cache. Filling a block is a read even when it's for a store.
+    let fill_kind = match kind {
+      AccessKind::InstructionFetch => AccessKind::InstructionFetch,
+      AccessKind::Load | AccessKind::Store => AccessKind::Load,
+    };
+    memory.read_block(
+      small_bound,
+      &mut self.sets[set_index].0[victim_idx].data,
+      fill_kind,
+    );
+    self.stats.words_from_memory += self.block_size;
+    utils::print_action(small_bound, self.block_size, memory.name(), &self.name);
+    (set_index, victim_idx)
+  }
+
+  pub fn stats(&self) -> &CacheStats {
+    &self.stats
+  }
+
+  /// Cycles taken by each access to the cache
+  pub fn hit_latency(&self) -> usize {
+    self.hit_latency
+  }
+
+  pub fn set_count(&self) -> usize {
+    self.set_count
+  }
+
+  /// How many bits of an address are the tag, the set index and the block offset, in that
+  /// order. Addresses are just wide enough to cover memory.
+  pub fn address_split(&self) -> (usize, usize, usize) {
+    let address_bits = NUM_MEMORY.trailing_zeros() as usize;
+    let index_bits = self.set_bit_count + self.offset_bit_count;
+    (
+      address_bits.saturating_sub(index_bits),
+      self.set_bit_count,
+      self.offset_bit_count,
+    )
+  }
+
+  /// The number of words in each block
+  pub fn block_size(&self) -> usize {
+    self.block_size
+  }
+
+  /// The number of blocks in each set
+  pub fn associativity(&self) -> usize {
+    self.sets[0].0.len()
+  }
+
+  /// The blocks making up one set of the cache
+  pub fn blocks(&self, set_index: usize) -> &[Block] {
+    &self.sets[set_index].0
+  }
+
+  /// Overwrites a word wherever this cache holds it (a cached block or the write buffer) without
+  /// counting as an access, so a word changed from outside the program stays consistent
+  pub fn poke(&mut self, address: usize, value: i32) {
+    if let Some((set_index, block_index)) = self.find_block(address) {
+      let block_offset = self.get_block_offset(address);
+      self.sets[set_index].0[block_index].data[block_offset] = value;
+    }
+    for entry in self.write_buffer.iter_mut() {
+      if entry.0 == address {
+        entry.1 = value;
+      }
+    }
+  }
+
+  /// Looks up a word without counting as a use of its block, None if the word isn't cached
+  /// or waiting in the write buffer
+  pub fn peek(&self, address: usize) -> Option<i32> {
+    if let Some((set_index, block_index)) = self.find_block(address) {
+      return Some(self.sets[set_index].0[block_index].data[self.get_block_offset(address)]);
+    }
+    self
+      .write_buffer
+      .iter()
+      .find(|entry| entry.0 == address)
+      .map(|entry| entry.1)
+  }
 
-    // Now that the block is in the cache, we can recursively call the function to update the value
-    // at the given address.
-    self.set_value(address, memory, value);
+  /// For use with HALT instructions, writes back all dirty cache blocks and drains the write
+  /// buffer so the level below holds every store
+  pub fn flush(&mut self, memory: &mut dyn MemoryLevel) {
+    self.writeback_dirty_blocks(memory);
+    while !self.write_buffer.is_empty() {
+      self.drain_oldest_write(memory);
+    }
   }
 
-  /// For use with HALT instructions, writes back all dirty cache blocks before halting
-  pub fn writeback_dirty_blocks(&self, memory: &mut [i32]) {
-    for set in self.sets.iter() {
-      for block in set.0.iter() {
+  /// For use with HALT instructions, writes back all dirty cache blocks before halting. The
+  /// blocks are clean afterwards, so writing them back again does nothing.
+  pub fn writeback_dirty_blocks(&mut self, memory: &mut dyn MemoryLevel) {
+    for set_index in 0..self.sets.len() {
+      if !self.sets[set_index].0.iter().any(|block| block.dirty) {
+        continue;
+      }
+      self.remember_set(set_index);
+      for block in self.sets[set_index].0.iter_mut() {
         if !block.dirty {
           continue;
         }
 
-        let block_end_bound = block.starting_address + self.block_size;
-        let memory_block = &mut memory[block.starting_address..block_end_bound];
-        assert!(
-          memory_block.len() == block.data.len(),
-          "Cache block length does not match memory block length\ncache: {}\nmemory: {}",
-          block.data.len(),
-          memory_block.len(),
-        );
-        memory_block.copy_from_slice(&block.data);
+        memory.write_block(block.starting_address, &block.data);
+        block.dirty = false;
+        self.stats.halt_writebacks += 1;
+        self.stats.words_to_memory += self.block_size;
         utils::print_action(
           block.starting_address,
           self.block_size,
-          Action::CacheToMemory,
+          &self.name,
+          memory.name(),
         );
       }
     }
@@ -358,19 +1019,8 @@ impl Cache {
     for (i, set) in self.sets.iter().enumerate() {
       println!("\tSet #{}", i);
       for (j, block) in set.0.iter().enumerate() {
-        let valid: String;
-        let dirty: String;
-        if block.valid {
-          valid = "V".to_string();
-        } else {
-          valid = "N".to_string();
-        }
-
-        if block.dirty {
-          dirty = "D".to_string();
-        } else {
-          dirty = "C".to_string();
-        }
+        let valid = if block.valid { "V" } else { "N" };
+        let dirty = if block.dirty { "D" } else { "C" };
 
         println!("\t\tBlock #{} -- {} {}", j, valid, dirty);
         for (k, row) in block.data.iter().enumerate() {
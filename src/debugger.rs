@@ -0,0 +1,388 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! An interactive debugger for LC3100 programs, built on stepping a [`Machine`].
+//!
+//! Commands are read one line at a time (see [`HELP`]), and the program runs until it has
+//! taken the steps asked for, reaches a breakpoint, changes a watched register or word, or
+//! halts.
+
+use crate::error::SimError;
+use crate::utils::{print_cache_set, print_state};
+use crate::{Machine, NUM_MEMORY, NUM_REGISTER};
+use std::io::{self, BufRead, Write};
+
+pub const HELP: &str = "commands:
+  step [N], next [N]        execute N instructions (default 1)
+  back [N]                  undo the last N instructions (default 1)
+  continue                  run until a breakpoint, a watchpoint or halt
+  break PC|LABEL            stop before executing the instruction at PC or LABEL
+  watch reg R               stop after register R changes
+  watch mem ADDRESS|LABEL   stop after the word at ADDRESS or LABEL changes
+  delete ID                 remove a breakpoint or watchpoint
+  info                      list breakpoints and watchpoints
+  print regs                print the registers
+  print pc                  print the PC
+  print mem START [END]     print the words from START to END (labels work too)
+  print cache [SET]         print one set (or every set) of each cache
+  print state               print the whole machine state
+  set reg R VALUE           change a register
+  set mem ADDRESS VALUE     change a word of memory (and any cached copies)
+  set pc VALUE              change the PC
+  help                      print this message
+  quit                      exit the debugger
+an empty line repeats the last command";
+
+/// Something the debugger stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Point {
+  /// Stop before executing the instruction at this PC
+  Breakpoint(u32),
+  /// Stop after an instruction changes this register
+  Register(usize),
+  /// Stop after an instruction changes the word at this address
+  Memory(usize),
+}
+
+/// Why the debugger stopped running the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+  /// Every step asked for was taken
+  Stepped,
+  Breakpoint {
+    id: usize,
+    pc: u32,
+  },
+  Watchpoint {
+    id: usize,
+    old: i32,
+    new: i32,
+  },
+  Halted,
+}
+
+pub struct Debugger {
+  machine: Machine,
+  // Breakpoints and watchpoints by id, along with the last value seen for watchpoints
+  points: Vec<(usize, Point, i32)>,
+  next_id: usize,
+  last_command: String,
+}
+
+impl Debugger {
+  /// Starts debugging `machine`, recording its history from here on so it can step back
+  pub fn new(mut machine: Machine) -> Debugger {
+    machine.record_history(true);
+    Debugger {
+      machine,
+      points: Vec::new(),
+      next_id: 1,
+      last_command: String::new(),
+    }
+  }
+
+  pub fn machine(&self) -> &Machine {
+    &self.machine
+  }
+
+  pub fn machine_mut(&mut self) -> &mut Machine {
+    &mut self.machine
+  }
+
+  /// Adds a breakpoint or watchpoint, returning its id
+  pub fn add_point(&mut self, point: Point) -> usize {
+    let id = self.next_id;
+    self.next_id += 1;
+    let value = self.watched_value(point);
+    self.points.push((id, point, value));
+    id
+  }
+
+  /// Removes a breakpoint or watchpoint, false if there is none with that id
+  pub fn delete_point(&mut self, id: usize) -> bool {
+    let count = self.points.len();
+    self.points.retain(|(point_id, _, _)| *point_id != id);
+    self.points.len() != count
+  }
+
+  /// Executes up to `count` instructions, stopping early on breakpoints, watchpoints or halt
+  pub fn step(&mut self, count: usize) -> Result<Stop, SimError> {
+    self.run(Some(count))
+  }
+
+  /// Undoes up to `count` instructions, returning how many were undone
+  pub fn step_back(&mut self, count: usize) -> usize {
+    self.machine.step_back(count)
+  }
+
+  /// Runs until a breakpoint, a watchpoint or halt
+  pub fn continue_running(&mut self) -> Result<Stop, SimError> {
+    self.run(None)
+  }
+
+  fn run(&mut self, limit: Option<usize>) -> Result<Stop, SimError> {
+    // Values may have been changed by hand since the last stop
+    for idx in 0..self.points.len() {
+      self.points[idx].2 = self.watched_value(self.points[idx].1);
+    }
+
+    let mut steps = 0;
+    loop {
+      if self.machine.is_halted() {
+        return Ok(Stop::Halted);
+      }
+      if limit == Some(steps) {
+        return Ok(Stop::Stepped);
+      }
+      // A breakpoint at the PC we started from has already been hit
+      if steps > 0 {
+        let pc = self.machine.pc();
+        let hit = self
+          .points
+          .iter()
+          .find(|(_, point, _)| *point == Point::Breakpoint(pc));
+        if let Some((id, _, _)) = hit {
+          return Ok(Stop::Breakpoint { id: *id, pc });
+        }
+      }
+
+      self.machine.step()?;
+      steps += 1;
+
+      for idx in 0..self.points.len() {
+        let (id, point, old) = self.points[idx];
+        let new = self.watched_value(point);
+        if new != old {
+          self.points[idx].2 = new;
+          return Ok(Stop::Watchpoint { id, old, new });
+        }
+      }
+    }
+  }
+
+  fn watched_value(&self, point: Point) -> i32 {
+    match point {
+      Point::Breakpoint(_) => 0,
+      Point::Register(register) => self.machine.register(register),
+      Point::Memory(address) => self.machine.memory_system().peek_memory(address),
+    }
+  }
+
+  /// Reads and runs commands until `quit` or the end of the input
+  pub fn repl<R: BufRead>(&mut self, input: R) -> io::Result<()> {
+    print!("(debug) ");
+    io::stdout().flush()?;
+    for line in input.lines() {
+      if !self.execute(&line?) {
+        break;
+      }
+      print!("(debug) ");
+      io::stdout().flush()?;
+    }
+    println!();
+    Ok(())
+  }
+
+  /// Runs a single command line, returns false once the user quits
+  pub fn execute(&mut self, line: &str) -> bool {
+    let line = if line.trim().is_empty() {
+      self.last_command.clone()
+    } else {
+      line.trim().to_string()
+    };
+    self.last_command = line.clone();
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match self.command(&words) {
+      Ok(keep_going) => keep_going,
+      Err(message) => {
+        println!("{}", message);
+        true
+      }
+    }
+  }
+
+  fn command(&mut self, words: &[&str]) -> Result<bool, String> {
+    match words {
+      [] => {}
+      ["step" | "s" | "next" | "n", rest @ ..] => {
+        let count = match rest {
+          [] => 1,
+          [count] => parse_number(count)?,
+          _ => return Err("usage: step [N]".to_string()),
+        };
+        let stop = self.step(count);
+        self.report(stop);
+      }
+      ["back" | "reverse-step" | "rs", rest @ ..] => {
+        let count = match rest {
+          [] => 1,
+          [count] => parse_number(count)?,
+          _ => return Err("usage: back [N]".to_string()),
+        };
+        let undone = self.step_back(count);
+        if undone < count {
+          println!("reached the start of the recorded history");
+        }
+        println!("stepped back {}, pc {}", undone, self.machine.pc());
+      }
+      ["continue" | "c"] => {
+        let stop = self.continue_running();
+        self.report(stop);
+      }
+      ["break" | "b", location] => {
+        let pc = self.parse_address(location)? as u32;
+        let id = self.add_point(Point::Breakpoint(pc));
+        println!("breakpoint {} at pc {}", id, pc);
+      }
+      ["watch" | "w", "reg", register] => {
+        let register = parse_register(register)?;
+        let id = self.add_point(Point::Register(register));
+        println!("watchpoint {} on reg[ {} ]", id, register);
+      }
+      ["watch" | "w", "mem", address] => {
+        let address = self.parse_address(address)?;
+        let id = self.add_point(Point::Memory(address));
+        println!("watchpoint {} on mem[ {} ]", id, address);
+      }
+      ["delete" | "d", id] => {
+        if !self.delete_point(parse_number(id)?) {
+          return Err(format!("no breakpoint or watchpoint {}", id));
+        }
+      }
+      ["info" | "i"] => {
+        for (id, point, _) in self.points.iter() {
+          match point {
+            Point::Breakpoint(pc) => println!("{}: breakpoint at pc {}", id, pc),
+            Point::Register(register) => println!("{}: watchpoint on reg[ {} ]", id, register),
+            Point::Memory(address) => println!("{}: watchpoint on mem[ {} ]", id, address),
+          }
+        }
+      }
+      ["print" | "p", what @ ..] => self.print(what)?,
+      ["set", "reg", register, value] => {
+        let register = parse_register(register)?;
+        self.machine.set_register(register, parse_value(value)?);
+      }
+      ["set", "mem", address, value] => {
+        let address = self.parse_address(address)?;
+        self.machine.poke_memory(address, parse_value(value)?);
+      }
+      ["set", "pc", value] => {
+        let pc = self.parse_address(value)? as u32;
+        self.machine.set_pc(pc);
+      }
+      ["help" | "h"] => println!("{}", HELP),
+      ["quit" | "q"] => return Ok(false),
+      _ => return Err(format!("unknown command `{}`, try `help`", words.join(" "))),
+    }
+    Ok(true)
+  }
+
+  fn print(&self, what: &[&str]) -> Result<(), String> {
+    match what {
+      ["regs" | "reg"] => {
+        for (register, value) in self.machine.registers().iter().enumerate() {
+          println!("reg[ {} ] {}", register, value);
+        }
+      }
+      ["pc"] => println!("pc {}", self.machine.pc()),
+      ["mem", start, rest @ ..] => {
+        let start = self.parse_address(start)?;
+        let end = match rest {
+          [] => start,
+          [end] => self.parse_address(end)?,
+          _ => return Err("usage: print mem START [END]".to_string()),
+        };
+        let memory = self.machine.memory_system();
+        for address in start..=end {
+          println!("mem[ {} ] {}", address, memory.peek_memory(address));
+        }
+      }
+      ["cache", rest @ ..] => {
+        for cache in self.machine.memory_system().all_caches() {
+          match rest {
+            [] => {
+              for set_index in 0..cache.set_count() {
+                print_cache_set(cache, set_index);
+              }
+            }
+            [set_index] => {
+              let set_index = parse_number(set_index)?;
+              if set_index >= cache.set_count() {
+                return Err(format!("{} has no set {}", cache.name(), set_index));
+              }
+              print_cache_set(cache, set_index);
+            }
+            _ => return Err("usage: print cache [SET]".to_string()),
+          }
+        }
+      }
+      ["state"] => print_state(&self.machine),
+      _ => return Err("usage: print regs|pc|mem|cache|state, try `help`".to_string()),
+    }
+    Ok(())
+  }
+
+  fn report(&self, stop: Result<Stop, SimError>) {
+    match stop {
+      Ok(Stop::Stepped) => println!("pc {}", self.machine.pc()),
+      Ok(Stop::Breakpoint { id, pc }) => println!("breakpoint {}, pc {}", id, pc),
+      Ok(Stop::Watchpoint { id, old, new }) => {
+        let watched = match self.points.iter().find(|(point_id, _, _)| *point_id == id) {
+          Some((_, Point::Register(register), _)) => format!("reg[ {} ]", register),
+          Some((_, Point::Memory(address), _)) => format!("mem[ {} ]", address),
+          _ => String::new(),
+        };
+        println!(
+          "watchpoint {}: {} {} -> {}, pc {}",
+          id,
+          watched,
+          old,
+          new,
+          self.machine.pc()
+        );
+      }
+      Ok(Stop::Halted) => println!(
+        "machine halted, total of {} instructions executed",
+        self.machine.instructions_executed()
+      ),
+      Err(error) => println!("[ERROR]: {}", error),
+    }
+  }
+
+  /// Parses an address (or PC) given as a number or a label from the program
+  fn parse_address(&self, word: &str) -> Result<usize, String> {
+    let address = match word.parse::<usize>() {
+      Ok(address) => address,
+      Err(_) => self
+        .machine
+        .label_address(word)
+        .ok_or_else(|| format!("`{}` is not an address or a label", word))?,
+    };
+    if address >= NUM_MEMORY {
+      return Err(format!("{} is outside of memory", address));
+    }
+    Ok(address)
+  }
+}
+
+fn parse_number(word: &str) -> Result<usize, String> {
+  word
+    .parse()
+    .map_err(|_| format!("`{}` is not a number", word))
+}
+
+fn parse_value(word: &str) -> Result<i32, String> {
+  word
+    .parse()
+    .map_err(|_| format!("`{}` is not a 32-bit integer", word))
+}
+
+/// Parses a register number, with or without an `r` in front
+fn parse_register(word: &str) -> Result<usize, String> {
+  let register = parse_number(word.trim_start_matches('r'))?;
+  if register >= NUM_REGISTER {
+    return Err(format!("there is no register {}", register));
+  }
+  Ok(register)
+}
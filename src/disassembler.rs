@@ -0,0 +1,180 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Turns LC3100 machine code back into assembly.
+//!
+//! Instructions are found by following the program from address 0: falling through to the
+//! next word, taking both sides of every `beq`, entering subroutines whose addresses are loaded
+//! with `lw 0 R ADDRESS` into a register a `jalr` jumps to, and coming back after every `jalr`
+//! call. A `jalr` through a register another `jalr` stored a return address in is a return,
+//! so nothing after it is followed. Every word that isn't reached this way is data and is
+//! written as `.fill`. Branch targets get synthetic labels (`L` followed by the address), so
+//! assembling the output gives back the same machine code.
+
+use crate::parser::{encode, parse_instruction};
+use crate::types::*;
+use crate::NUM_REGISTER;
+
+/// Disassembles machine code, one line of assembly per word
+pub fn disassemble(words: &[i32]) -> String {
+  let code = find_instructions(words);
+  let mut labelled = vec![false; words.len()];
+  for (address, word) in words.iter().enumerate() {
+    if code[address] {
+      if let Some(target) = branch_target(address, *word, words.len()) {
+        labelled[target] = true;
+      }
+    }
+  }
+
+  let mut output = String::new();
+  for (address, word) in words.iter().enumerate() {
+    let label = if labelled[address] {
+      label_name(address)
+    } else {
+      String::new()
+    };
+    let text = if code[address] {
+      format_instruction(address, *word, &labelled)
+    } else {
+      format!(".fill {}", word)
+    };
+    output.push_str(&format!("{:<7}{}\n", label, text));
+  }
+  output
+}
+
+/// Marks the words that can be reached as instructions by following the program from address 0
+fn find_instructions(words: &[i32]) -> Vec<bool> {
+  // Return address registers are only known once the calls are found, so keep following the
+  // program until no new ones turn up
+  let mut link_registers = [false; NUM_REGISTER];
+  loop {
+    let (code, found) = follow_program(words, &link_registers);
+    if found == link_registers {
+      return code;
+    }
+    for (link, found) in link_registers.iter_mut().zip(found) {
+      *link |= found;
+    }
+  }
+}
+
+/// Follows the program from address 0, treating `jalr` through `link_registers` as returns.
+/// Returns the words reached along with the registers reached `jalr` calls link through.
+fn follow_program(
+  words: &[i32],
+  link_registers: &[bool; NUM_REGISTER],
+) -> (Vec<bool>, [bool; NUM_REGISTER]) {
+  let mut code = vec![false; words.len()];
+  let mut found = [false; NUM_REGISTER];
+  let mut entries = vec![0];
+  // Addresses loaded into each register by `lw 0 R ADDRESS`, and the registers `jalr` jumps to
+  let mut loaded: [Vec<usize>; NUM_REGISTER] = Default::default();
+  let mut jump_registers = [false; NUM_REGISTER];
+
+  while let Some(address) = entries.pop() {
+    if address >= words.len() || code[address] || !is_well_formed(words[address]) {
+      continue;
+    }
+    code[address] = true;
+    let next = address + 1;
+
+    match parse_instruction(words[address]) {
+      OpType::O(OType {
+        code: OTypeOpcode::Halt,
+      }) => {}
+      OpType::I(IType {
+        code: ITypeOpcode::BranchEq,
+        register_a,
+        register_b,
+        ..
+      }) => {
+        entries.extend(branch_target(address, words[address], words.len()));
+        // `beq R R` always branches
+        if register_a != register_b {
+          entries.push(next);
+        }
+      }
+      OpType::I(IType {
+        code: ITypeOpcode::LoadWord,
+        register_a: 0,
+        register_b,
+        offset,
+      }) => {
+        let pointer = usize::try_from(offset)
+          .ok()
+          .and_then(|source| words.get(source))
+          .and_then(|value| usize::try_from(*value).ok())
+          .filter(|pointer| *pointer < words.len());
+        if let Some(pointer) = pointer {
+          loaded[register_b as usize].push(pointer);
+          if jump_registers[register_b as usize] {
+            entries.push(pointer);
+          }
+        }
+        entries.push(next);
+      }
+      OpType::J(j_type) => {
+        let (register_a, register_b) = (j_type.register_a as usize, j_type.register_b as usize);
+        if register_a == register_b {
+          // The return address is what gets jumped to
+          entries.push(next);
+        } else if link_registers[register_a] {
+          // A return, the caller is followed from its own `jalr`
+          found[register_b] = true;
+        } else {
+          found[register_b] = true;
+          jump_registers[register_a] = true;
+          entries.extend(loaded[register_a].iter());
+          // Subroutines return to the instruction after the call
+          entries.push(next);
+        }
+      }
+      _ => entries.push(next),
+    }
+  }
+  (code, found)
+}
+
+/// True if the word is an instruction the assembler could have produced, with the opcode's
+/// unused bits all zero
+fn is_well_formed(word: i32) -> bool {
+  encode(&parse_instruction(word)) == word
+}
+
+/// The address a `beq` branches to, if the word is a `beq` that stays inside the program
+fn branch_target(address: usize, word: i32, length: usize) -> Option<usize> {
+  match parse_instruction(word) {
+    OpType::I(IType {
+      code: ITypeOpcode::BranchEq,
+      offset,
+      ..
+    }) => {
+      let target = address as i64 + 1 + offset as i64;
+      usize::try_from(target)
+        .ok()
+        .filter(|target| *target < length)
+    }
+    _ => None,
+  }
+}
+
+fn label_name(address: usize) -> String {
+  format!("L{}", address)
+}
+
+fn format_instruction(address: usize, word: i32, labelled: &[bool]) -> String {
+  match (
+    parse_instruction(word),
+    branch_target(address, word, labelled.len()),
+  ) {
+    (OpType::I(i_type), Some(target)) if labelled[target] => format!(
+      "{} {} {} {}",
+      i_type.code,
+      i_type.register_a,
+      i_type.register_b,
+      label_name(target)
+    ),
+    (instruction, _) => instruction.to_string(),
+  }
+}
@@ -0,0 +1,99 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+use crate::assembler::AssembleError;
+use std::fmt;
+
+/// Everything that can go wrong while loading or running a program
+#[derive(Debug)]
+pub enum SimError {
+  /// The cache arguments don't describe a usable cache
+  InvalidCache(String),
+  /// The branch predictor arguments don't describe usable predictor tables
+  InvalidPredictor(String),
+  /// The input file couldn't be opened or read
+  Io { path: String, message: String },
+  /// A line of machine code isn't a decimal integer (line numbers start at 1)
+  BadInput { line: usize, text: String },
+  /// A line of an address trace couldn't be read (line numbers start at 1)
+  BadTrace { line: usize, message: String },
+  /// A line of a machine snapshot couldn't be read (line numbers start at 1)
+  BadSnapshot { line: usize, message: String },
+  /// A snapshot doesn't describe a machine's memory
+  InvalidSnapshot(String),
+  /// The program doesn't fit in memory
+  ProgramTooLarge { words: usize },
+  /// The assembly source couldn't be assembled
+  Assemble(AssembleError),
+  /// The PC left the bounds of memory
+  PcOutOfRange { pc: i64 },
+  /// A `lw` or `sw` computed an address outside of memory
+  AddressOutOfRange { address: i64, pc: u32 },
+  /// An `add` overflowed while overflow is set to trap
+  ArithmeticOverflow { pc: u32, reg_a: i32, reg_b: i32 },
+}
+
+impl SimError {
+  /// The process exit code for this class of error
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      SimError::InvalidCache(_) | SimError::InvalidPredictor(_) => 2,
+      SimError::Io { .. } => 3,
+      SimError::BadInput { .. }
+      | SimError::BadTrace { .. }
+      | SimError::BadSnapshot { .. }
+      | SimError::InvalidSnapshot(_)
+      | SimError::ProgramTooLarge { .. } => 4,
+      SimError::Assemble(_) => 5,
+      SimError::PcOutOfRange { .. } => 6,
+      SimError::AddressOutOfRange { .. } => 7,
+      SimError::ArithmeticOverflow { .. } => 8,
+    }
+  }
+}
+
+impl fmt::Display for SimError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SimError::InvalidCache(message)
+      | SimError::InvalidPredictor(message)
+      | SimError::InvalidSnapshot(message) => {
+        write!(f, "{}", message)
+      }
+      SimError::Io { path, message } => write!(f, "Couldn't open {}: {}", path, message),
+      SimError::BadInput { line, text } => write!(
+        f,
+        "Line {} of the machine code is not a number: {:?}",
+        line, text
+      ),
+      SimError::BadTrace { line, message } => {
+        write!(f, "Line {} of the address trace {}", line, message)
+      }
+      SimError::BadSnapshot { line, message } => {
+        write!(f, "Line {} of the snapshot {}", line, message)
+      }
+      SimError::ProgramTooLarge { words } => write!(
+        f,
+        "The program is {} words long, which does not fit in memory",
+        words
+      ),
+      SimError::Assemble(error) => write!(f, "{}", error),
+      SimError::PcOutOfRange { pc } => write!(f, "The PC ({}) is outside of memory", pc),
+      SimError::AddressOutOfRange { address, pc } => write!(
+        f,
+        "Address {} is outside of memory (instruction at pc {})",
+        address, pc
+      ),
+      SimError::ArithmeticOverflow { pc, reg_a, reg_b } => write!(
+        f,
+        "Overflow adding {} and {} (instruction at pc {})",
+        reg_a, reg_b, pc
+      ),
+    }
+  }
+}
+
+impl From<AssembleError> for SimError {
+  fn from(error: AssembleError) -> SimError {
+    SimError::Assemble(error)
+  }
+}
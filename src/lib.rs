@@ -0,0 +1,32 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! LC3100 behavioral simulator (with caching)
+//!
+//! The [`Machine`] holds the processor state, the cache and memory, and can be driven one
+//! instruction at a time or run until it halts.
+
+pub mod address_trace;
+pub mod assembler;
+pub mod cache;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod loader;
+pub mod machine;
+pub mod memory;
+pub mod multicycle;
+pub mod parser;
+pub mod pipeline;
+pub mod predictor;
+pub mod replacement;
+pub mod snapshot;
+pub mod timing;
+pub mod trace;
+pub mod types;
+pub mod utils;
+
+pub use error::SimError;
+pub use machine::{Machine, OverflowPolicy, State};
+
+pub const NUM_MEMORY: usize = u16::MAX as usize + 1;
+pub const NUM_REGISTER: usize = 8;
@@ -0,0 +1,59 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+use crate::assembler::{self, Program};
+use crate::error::SimError;
+use std::fs;
+use std::path::Path;
+
+/// Reads the program at `path`, assembling it first if it is LC3100 assembly
+pub fn load_program(path: &Path, assembly: bool) -> Result<Program, SimError> {
+  let source = fs::read_to_string(path).map_err(|why| SimError::Io {
+    path: path.display().to_string(),
+    message: why.to_string(),
+  })?;
+
+  if assembly || is_assembly_source(path, &source) {
+    return Ok(assembler::assemble(&source)?);
+  }
+  parse_machine_code(&source)
+}
+
+/// Reads instructions from machine code, one decimal word per line
+pub fn parse_machine_code(source: &str) -> Result<Program, SimError> {
+  let mut words = Vec::new();
+  for (i, line) in source.lines().enumerate() {
+    match line.trim().parse() {
+      Ok(word) => words.push(word),
+      Err(_) => {
+        return Err(SimError::BadInput {
+          line: i + 1,
+          text: line.to_string(),
+        })
+      }
+    }
+  }
+
+  Ok(Program {
+    words,
+    labels: Vec::new(),
+  })
+}
+
+/// Guesses if the input is assembly, either from a `.as` extension (including files
+/// such as `test.as.2.2.2`) or from its first line starting with an opcode, after an
+/// optional label
+pub fn is_assembly_source(path: &Path, source: &str) -> bool {
+  let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+  if file_name
+    .split('.')
+    .skip(1)
+    .any(|extension| extension == "as")
+  {
+    return true;
+  }
+
+  match source.lines().next() {
+    Some(line) => line.split_whitespace().take(2).any(assembler::is_opcode),
+    None => false,
+  }
+}
@@ -0,0 +1,438 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+use crate::assembler::Program;
+use crate::cache::{AccessKind, Cache};
+use crate::error::SimError;
+use crate::memory::{MemorySystem, MemoryUndo};
+use crate::parser::parse_instruction;
+use crate::snapshot::Snapshot;
+use crate::timing::{MemoryTiming, TimingSummary};
+use crate::trace::TraceRecord;
+use crate::types::*;
+use crate::{NUM_MEMORY, NUM_REGISTER};
+
+pub struct State {
+  pub(crate) pc: u32,
+  pub(crate) reg: [i32; NUM_REGISTER],
+}
+
+/// What `add` does when the result doesn't fit in 32 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverflowPolicy {
+  /// Wrap around like two's complement hardware does
+  Wrap,
+  /// Stop the machine with an error
+  Trap,
+}
+
+/// An LC3100 machine, the processor state along with the caches sitting in front of memory
+pub struct Machine {
+  state: State,
+  memory: MemorySystem,
+  labels: Vec<(String, usize)>,
+  overflow: OverflowPolicy,
+  halted: bool,
+  instructions_executed: usize,
+  // One entry per instruction executed while history is being recorded, newest last
+  history: Option<Vec<Undo>>,
+  last_step: Option<TraceRecord>,
+}
+
+/// Everything needed to undo one instruction
+struct Undo {
+  pc: u32,
+  registers: [i32; NUM_REGISTER],
+  halted: bool,
+  instructions_executed: usize,
+  memory: MemoryUndo,
+}
+
+impl State {
+  fn new() -> State {
+    State {
+      pc: 0,
+      reg: [0; NUM_REGISTER],
+    }
+  }
+}
+
+impl Machine {
+  pub fn new(cache: Cache) -> Machine {
+    Machine::with_hierarchy(vec![cache])
+  }
+
+  /// Creates a machine with a multi-level cache hierarchy, `caches` are ordered from L1 down to
+  /// the last level before memory. With more than one level the caches are renamed "L1 cache",
+  /// "L2 cache" and so on.
+  pub fn with_hierarchy(caches: Vec<Cache>) -> Machine {
+    Machine::with_memory(MemorySystem::new(caches, None))
+  }
+
+  /// Creates a machine with separate L1 instruction and data caches (a Harvard L1), both in
+  /// front of the unified `lower` levels (which can be empty to go straight to memory). The
+  /// caches are renamed "instruction cache" and "data cache", with an "L1" prefix and "L2
+  /// cache" and so on for the lower levels if there are any.
+  pub fn with_split_l1(instruction_cache: Cache, data_cache: Cache, lower: Vec<Cache>) -> Machine {
+    let mut caches = vec![data_cache];
+    caches.extend(lower);
+    Machine::with_memory(MemorySystem::new(caches, Some(instruction_cache)))
+  }
+
+  /// Creates a machine in front of `memory`, which should still be empty
+  pub fn with_memory(memory: MemorySystem) -> Machine {
+    Machine {
+      state: State::new(),
+      memory,
+      labels: Vec::new(),
+      overflow: OverflowPolicy::Wrap,
+      halted: false,
+      instructions_executed: 0,
+      history: None,
+      last_step: None,
+    }
+  }
+
+  pub fn set_overflow_policy(&mut self, overflow: OverflowPolicy) {
+    self.overflow = overflow;
+  }
+
+  /// Changes how long main memory takes for each transfer from now on
+  pub fn set_memory_timing(&mut self, timing: MemoryTiming) {
+    self.memory.set_memory_timing(timing);
+  }
+
+  /// Copies the program into memory starting at address 0 and keeps its labels
+  pub fn load(&mut self, program: &Program) -> Result<(), SimError> {
+    self.memory.load(program)?;
+    self.labels = program.labels.clone();
+    if let Some(history) = self.history.as_mut() {
+      history.clear();
+    }
+    self.last_step = None;
+    Ok(())
+  }
+
+  /// Turns recording an undo log of every instruction on or off, so `step_back` can undo them.
+  /// Turning it off throws away the history recorded so far.
+  pub fn record_history(&mut self, enabled: bool) {
+    self.history = if enabled { Some(Vec::new()) } else { None };
+  }
+
+  /// The number of instructions that can currently be undone
+  pub fn history_len(&self) -> usize {
+    self.history.as_ref().map_or(0, Vec::len)
+  }
+
+  /// Undoes up to `count` of the most recent instructions executed while history was being
+  /// recorded, restoring the registers, PC, memory and every cache (blocks, replacement policy
+  /// bookkeeping and statistics). Changes made by hand are not part of the history. Returns the
+  /// number of instructions undone.
+  pub fn step_back(&mut self, count: usize) -> usize {
+    let Some(history) = self.history.as_mut() else {
+      return 0;
+    };
+
+    let mut undone = 0;
+    while undone < count {
+      let Some(undo) = history.pop() else {
+        break;
+      };
+
+      self.state.pc = undo.pc;
+      self.state.reg = undo.registers;
+      self.halted = undo.halted;
+      self.instructions_executed = undo.instructions_executed;
+      self.memory.undo(undo.memory);
+      undone += 1;
+    }
+    if undone > 0 {
+      self.last_step = None;
+    }
+    undone
+  }
+
+  /// Fetches, decodes and executes a single instruction. Returns false without doing
+  /// anything if the machine has already halted.
+  pub fn step(&mut self) -> Result<bool, SimError> {
+    if self.halted {
+      return Ok(false);
+    }
+    if self.history.is_none() {
+      return self.execute();
+    }
+
+    let pc = self.state.pc;
+    let registers = self.state.reg;
+    let halted = self.halted;
+    let instructions_executed = self.instructions_executed;
+    self.memory.start_undo();
+
+    // Even an instruction that fails part way through is recorded, so it can be undone too
+    let result = self.execute();
+
+    if let (Some(history), Some(memory)) = (self.history.as_mut(), self.memory.finish_undo()) {
+      history.push(Undo {
+        pc,
+        registers,
+        halted,
+        instructions_executed,
+        memory,
+      });
+    }
+    result
+  }
+
+  /// Executes the instruction at the PC
+  fn execute(&mut self) -> Result<bool, SimError> {
+    self.last_step = None;
+    let state = &mut self.state;
+    let memory = &mut self.memory;
+
+    let pc = state.pc;
+    if pc as usize >= NUM_MEMORY {
+      return Err(SimError::PcOutOfRange { pc: pc as i64 });
+    }
+    let hits = memory.fetch_cache().stats().hits();
+    let word = memory.fetch(pc as usize);
+    let fetch_hit = memory.fetch_cache().stats().hits() > hits;
+    state.pc += 1;
+
+    let current_instruction = parse_instruction(word);
+    let mut register_written = None;
+    let mut memory_access = None;
+    let data_hits = memory.cache().stats().hits();
+    match current_instruction {
+      OpType::O(o_type) => match o_type.code {
+        OTypeOpcode::Halt => {
+          self.halted = true;
+          // Not sure if writebacks are necessary for dirty blocks on halt,
+          // if not just comment out the line below.
+          memory.flush();
+        }
+        OTypeOpcode::NoOp => {}
+      },
+      OpType::J(j_type) => match j_type.code {
+        JTypeOpcode::JumpAndLink => {
+          // PC+1 is stored before reading regA, so if regA and regB are the
+          // same register the net effect is jumping to PC+1. The target is
+          // checked before regB is written so a bad jump leaves it untouched
+          let return_address = state.pc as i32;
+          let target = if j_type.register_a == j_type.register_b {
+            return_address
+          } else {
+            state.reg[j_type.register_a as usize]
+          };
+          state.pc = jump_target(target as i64)?;
+          state.reg[j_type.register_b as usize] = return_address;
+          register_written = Some((j_type.register_b as usize, return_address));
+        }
+      },
+      OpType::R(r_type) => match r_type.code {
+        RTypeOpcode::Add => {
+          let reg_a = state.reg[r_type.register_a as usize];
+          let reg_b = state.reg[r_type.register_b as usize];
+          state.reg[r_type.destination as usize] = match self.overflow {
+            OverflowPolicy::Wrap => reg_a.wrapping_add(reg_b),
+            OverflowPolicy::Trap => reg_a
+              .checked_add(reg_b)
+              .ok_or(SimError::ArithmeticOverflow { pc, reg_a, reg_b })?,
+          };
+          let destination = r_type.destination as usize;
+          register_written = Some((destination, state.reg[destination]));
+        }
+        RTypeOpcode::Nand => {
+          let reg_a = state.reg[r_type.register_a as usize];
+          let reg_b = state.reg[r_type.register_b as usize];
+          state.reg[r_type.destination as usize] = !(reg_a & reg_b);
+          register_written = Some((r_type.destination as usize, !(reg_a & reg_b)));
+        }
+      },
+      OpType::I(i_type) => match i_type.code {
+        ITypeOpcode::LoadWord => {
+          let reg_a = state.reg[i_type.register_a as usize];
+          let address = data_address(reg_a, i_type.offset, pc)?;
+          let value = memory.load_word(address);
+          state.reg[i_type.register_b as usize] = value;
+          register_written = Some((i_type.register_b as usize, value));
+          memory_access = Some((AccessKind::Load, address));
+        }
+        ITypeOpcode::StoreWord => {
+          let reg_a = state.reg[i_type.register_a as usize];
+          let address = data_address(reg_a, i_type.offset, pc)?;
+          let value = state.reg[i_type.register_b as usize];
+          memory.store_word(address, value);
+          memory_access = Some((AccessKind::Store, address));
+        }
+        ITypeOpcode::BranchEq => {
+          let reg_a = state.reg[i_type.register_a as usize];
+          let reg_b = state.reg[i_type.register_b as usize];
+          if reg_a == reg_b {
+            state.pc = jump_target(state.pc as i64 + i_type.offset as i64)?;
+          }
+        }
+      },
+    }
+
+    self.instructions_executed += 1;
+    self.last_step = Some(TraceRecord {
+      cycle: self.instructions_executed,
+      pc,
+      word,
+      instruction: current_instruction,
+      register_written,
+      memory_access,
+      fetch_hit,
+      data_hit: memory_access.map(|_| memory.cache().stats().hits() > data_hits),
+    });
+    Ok(true)
+  }
+
+  /// Runs until the machine halts, returning the total number of instructions executed
+  pub fn run(&mut self) -> Result<usize, SimError> {
+    while self.step()? {}
+    Ok(self.instructions_executed)
+  }
+
+  /// Runs until `condition` holds before the next instruction or the machine halts.
+  /// Returns true if the condition was met.
+  pub fn run_until<F>(&mut self, mut condition: F) -> Result<bool, SimError>
+  where
+    F: FnMut(&Machine) -> bool,
+  {
+    while !self.halted {
+      if condition(self) {
+        return Ok(true);
+      }
+      self.step()?;
+    }
+    Ok(false)
+  }
+
+  pub fn pc(&self) -> u32 {
+    self.state.pc
+  }
+
+  pub fn set_pc(&mut self, pc: u32) {
+    self.state.pc = pc;
+  }
+
+  pub fn registers(&self) -> &[i32] {
+    &self.state.reg
+  }
+
+  pub fn register(&self, index: usize) -> i32 {
+    self.state.reg[index]
+  }
+
+  pub fn set_register(&mut self, index: usize, value: i32) {
+    self.state.reg[index] = value;
+  }
+
+  /// Changes the word at `address` in memory and in every cache holding it, without going
+  /// through the caches as an access
+  pub fn poke_memory(&mut self, address: usize, value: i32) {
+    self.memory.poke_memory(address, value);
+  }
+
+  pub fn state(&self) -> &State {
+    &self.state
+  }
+
+  /// Main memory and the caches in front of it
+  pub fn memory_system(&self) -> &MemorySystem {
+    &self.memory
+  }
+
+  pub fn labels(&self) -> &[(String, usize)] {
+    &self.labels
+  }
+
+  /// Looks up the address of a label from the loaded program
+  pub fn label_address(&self, label: &str) -> Option<usize> {
+    self
+      .labels
+      .iter()
+      .find(|(name, _)| name == label)
+      .map(|(_, address)| *address)
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+
+  pub fn instructions_executed(&self) -> usize {
+    self.instructions_executed
+  }
+
+  /// The cycles spent in the caches and main memory so far, see [`crate::timing`]
+  pub fn timing(&self) -> TimingSummary {
+    TimingSummary {
+      instructions: Some(self.instructions_executed),
+      ..self.memory.timing()
+    }
+  }
+
+  /// Copies out everything needed to resume the machine later from exactly where it is, see
+  /// [`crate::snapshot`]
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      pc: self.state.pc,
+      registers: self.state.reg,
+      halted: self.halted,
+      instructions_executed: self.instructions_executed,
+      memory_cycles: self.memory.memory_cycles(),
+      program_length: self.memory.num_memory(),
+      memory: self.memory.memory().to_vec(),
+      caches: self
+        .memory
+        .all_caches()
+        .into_iter()
+        .map(|cache| (cache.name().to_string(), cache.save()))
+        .collect(),
+    }
+  }
+
+  /// Puts the machine back the way it was when `snapshot` was taken. The machine has to have
+  /// been built with the same caches (geometry, replacement policy and names), nothing is
+  /// changed if it wasn't. The history for `step_back` is cleared.
+  pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SimError> {
+    self.memory.restore(
+      snapshot.memory,
+      snapshot.program_length,
+      snapshot.memory_cycles,
+      snapshot.caches,
+    )?;
+    self.state.pc = snapshot.pc;
+    self.state.reg = snapshot.registers;
+    self.halted = snapshot.halted;
+    self.instructions_executed = snapshot.instructions_executed;
+    if let Some(history) = self.history.as_mut() {
+      history.clear();
+    }
+    self.last_step = None;
+    Ok(())
+  }
+
+  /// What the most recently executed instruction did, None before the first step, after a
+  /// failed step or after stepping back
+  pub fn last_step(&self) -> Option<&TraceRecord> {
+    self.last_step.as_ref()
+  }
+}
+
+/// Checks that a branch or jump target is a valid PC
+pub(crate) fn jump_target(target: i64) -> Result<u32, SimError> {
+  if target < 0 || target >= NUM_MEMORY as i64 {
+    return Err(SimError::PcOutOfRange { pc: target });
+  }
+  Ok(target as u32)
+}
+
+/// Computes the address for `lw` and `sw`, which must land inside memory
+pub(crate) fn data_address(reg_a: i32, offset: i16, pc: u32) -> Result<usize, SimError> {
+  let address = reg_a as i64 + offset as i64;
+  if address < 0 || address >= NUM_MEMORY as i64 {
+    return Err(SimError::AddressOutOfRange { address, pc });
+  }
+  Ok(address as usize)
+}
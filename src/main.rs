@@ -1,143 +1,627 @@
 // Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
 
-pub mod cache;
-mod parser;
-pub mod types;
-pub mod utils;
-
-use parser::parse_instruction;
-use types::*;
-use utils::*;
-
-use clap::Parser;
-use std::fs::File;
-use std::io;
-use std::io::prelude::*;
-use std::path::Path;
-
-use crate::cache::Cache;
+use cda3100_lc_sim::address_trace::{parse_trace, run_trace};
+use cda3100_lc_sim::assembler::{self, Program};
+use cda3100_lc_sim::cache::{Cache, CacheConfig, WriteMissPolicy, WritePolicy};
+use cda3100_lc_sim::debugger::Debugger;
+use cda3100_lc_sim::disassembler::disassemble;
+use cda3100_lc_sim::loader::{load_program, parse_machine_code};
+use cda3100_lc_sim::memory::{name_levels, MemorySystem};
+use cda3100_lc_sim::multicycle::MulticycleMachine;
+use cda3100_lc_sim::pipeline::PipelineMachine;
+use cda3100_lc_sim::predictor::{last_branch, BranchStudy, PredictorConfig, PredictorKind};
+use cda3100_lc_sim::replacement::ReplacementKind;
+use cda3100_lc_sim::snapshot::Snapshot;
+use cda3100_lc_sim::timing::{MemoryTiming, TimedMemory, TimingSummary};
+use cda3100_lc_sim::trace::{din_line, TraceFormat, CSV_HEADER};
+use cda3100_lc_sim::utils::*;
+use cda3100_lc_sim::{Machine, OverflowPolicy, SimError, NUM_MEMORY};
 
-const NUM_MEMORY: usize = u16::MAX as usize + 1;
-const NUM_REGISTER: usize = 8;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 /// LC3100 behavioral simulator (with caching) written by Justin Schilleman (jas21ba)
 #[derive(Parser, Debug)]
-struct Args {
-  /// Path of the machine code file to be executed
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+  #[command(subcommand)]
+  command: Option<Command>,
+  #[command(flatten)]
+  run: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+  /// Assemble an LC3100 assembly file into machine code
+  Assemble(AssembleArgs),
+  /// Turn LC3100 machine code back into assembly
+  Disassemble(DisassembleArgs),
+  /// Step through a program interactively with breakpoints and watchpoints
+  Debug(Box<DebugArgs>),
+  /// Run the caches on their own against an address trace instead of a program
+  CacheTrace(Box<CacheTraceArgs>),
+  /// Run a program on a five-stage pipeline, printing the pipeline registers every cycle
+  Pipeline(Box<ModelArgs>),
+  /// Run a program and report how well different branch predictors would have done on it
+  BranchPredict(Box<BranchPredictArgs>),
+  /// Run a program on a multicycle datapath, printing the control state every cycle
+  Multicycle(Box<ModelArgs>),
+}
+
+#[derive(clap::Args, Debug)]
+struct AssembleArgs {
+  /// Path of the assembly file to be assembled
   input: String,
+  /// Path to write the machine code to (printed to stdout if not given)
+  output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct DisassembleArgs {
+  /// Path of the machine code file to be disassembled
+  input: String,
+  /// Path to write the assembly to (printed to stdout if not given)
+  output: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum StatsFormat {
+  Text,
+  Json,
+}
+
+#[derive(clap::Args, Debug)]
+struct CacheTraceArgs {
+  /// Path of the address trace, in Dinero `din` format (as written by `--din`) or the CSV
+  /// written by `--trace-format csv`
+  input: String,
+  #[command(flatten)]
+  cache: CacheArgs,
+  /// The format of the statistics printed at the end
+  #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+  stats: StatsFormat,
+}
+
+/// The cache hierarchy, shared by every command that simulates caches
+#[derive(clap::Args, Debug)]
+struct CacheArgs {
   /// The number of words found in a block
   block_size_in_words: usize,
   /// The number of sets in our cache
   number_of_sets: usize,
   /// The number of blocks found in each set
   blocks_per_set: usize,
+  /// The policy used to pick which block of a full set gets replaced
+  #[arg(long, value_enum, default_value_t = ReplacementKind::Lru)]
+  replacement: ReplacementKind,
+  /// Seed for the random replacement policy
+  #[arg(long, default_value_t = 1)]
+  seed: u64,
+  /// When stores that hit in the cache are written to memory
+  #[arg(long, value_enum, default_value_t = WritePolicy::Back)]
+  write: WritePolicy,
+  /// Whether a store that misses brings its block into the cache
+  #[arg(long, value_enum, default_value_t = WriteMissPolicy::Allocate)]
+  write_miss: WriteMissPolicy,
+  /// Number of words in the write buffer between the cache and memory (write-through only,
+  /// 0 for no write buffer)
+  #[arg(long, default_value_t = 0)]
+  write_buffer: usize,
+  /// Gives instruction fetches their own L1 cache, in the same format as `--l2`. The positional
+  /// cache arguments then describe the L1 data cache, and both share any lower levels
+  #[arg(long, value_name = "SPEC")]
+  icache: Option<CacheConfig>,
+  /// Adds an L2 cache below the cache given by the positional arguments, as `B.S.A` optionally
+  /// followed by policy options, e.g. `4.4.4,replacement=fifo,write=through,write-buffer=2`
+  /// (options are replacement, seed, write, write-miss and write-buffer)
+  #[arg(long, value_name = "SPEC")]
+  l2: Option<CacheConfig>,
+  /// Adds an L3 cache below the L2 cache, in the same format as `--l2`
+  #[arg(long, value_name = "SPEC", requires = "l2")]
+  l3: Option<CacheConfig>,
+  /// Print the size of each cache and how it splits addresses into tag, set and offset bits
+  /// before starting
+  #[arg(long)]
+  geometry: bool,
+  /// Cycles taken by every access to the L1 cache (lower levels take `hit-latency=N` in their
+  /// spec)
+  #[arg(long, default_value_t = 1)]
+  hit_latency: usize,
+  /// Cycles main memory takes before the first word of a transfer arrives
+  #[arg(long, default_value_t = MemoryTiming::default().latency)]
+  memory_latency: usize,
+  /// Cycles main memory takes for each word transferred
+  #[arg(long, default_value_t = MemoryTiming::default().word_cycles)]
+  word_cycles: usize,
+  /// Print the total cycles, CPI and average memory access time at the end
+  #[arg(long)]
+  timing: bool,
 }
 
-pub struct State {
-  pc: u32,
-  mem: [i32; NUM_MEMORY],
-  reg: [i32; NUM_REGISTER],
-  num_memory: u32,
+/// Running a program on one of the cycle-level models of the datapath
+#[derive(clap::Args, Debug)]
+struct ModelArgs {
+  /// Path of the machine code (or assembly) file to be executed
+  input: String,
+  #[command(flatten)]
+  cache: CacheArgs,
+  /// Treat the input as LC3100 assembly and assemble it before running (detected
+  /// automatically for `.as` files or input that starts with an opcode)
+  #[arg(short, long)]
+  assembly: bool,
+  /// Print cache statistics once the machine halts
+  #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+  stats: Option<StatsFormat>,
+  /// What happens when an `add` overflows
+  #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+  overflow: OverflowPolicy,
+}
+
+/// Loading a program into the debugger, which runs it one command at a time
+#[derive(clap::Args, Debug)]
+struct DebugArgs {
+  /// Path of the machine code (or assembly) file to be executed
+  input: String,
+  #[command(flatten)]
+  cache: CacheArgs,
+  /// Treat the input as LC3100 assembly and assemble it before running (detected
+  /// automatically for `.as` files or input that starts with an opcode)
+  #[arg(short, long)]
+  assembly: bool,
+  /// What happens when an `add` overflows
+  #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+  overflow: OverflowPolicy,
+  /// Resume from a snapshot saved with `--snapshot`, the cache arguments must be the same as
+  /// when it was saved
+  #[arg(long, value_name = "FILE")]
+  restore: Option<String>,
 }
 
-fn main() -> Result<(), i32> {
+#[derive(clap::Args, Debug)]
+struct BranchPredictArgs {
+  /// Path of the machine code (or assembly) file to be executed
+  input: String,
+  #[command(flatten)]
+  cache: CacheArgs,
+  /// Treat the input as LC3100 assembly and assemble it before running (detected
+  /// automatically for `.as` files or input that starts with an opcode)
+  #[arg(short, long)]
+  assembly: bool,
+  /// What happens when an `add` overflows
+  #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+  overflow: OverflowPolicy,
+  /// The predictors to compare, separated by commas (all of them if not given)
+  #[arg(long, value_enum, value_delimiter = ',')]
+  predictor: Vec<PredictorKind>,
+  /// Entries in the 1-bit, 2-bit and gshare tables
+  #[arg(long, default_value_t = PredictorConfig::default().table_size)]
+  table_size: usize,
+  /// Branches remembered in gshare's global history
+  #[arg(long, default_value_t = PredictorConfig::default().history_bits)]
+  history_bits: usize,
+  /// Gives every predictor a branch target buffer with this many entries, branches predicted
+  /// taken then only count as taken if their target is in it
+  #[arg(long, value_name = "ENTRIES")]
+  btb: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+// Clap leaves the group of arguments with a flattened field empty, so `Cli` would never see
+// the simulator arguments as given without naming one of them here
+#[group(arg = "input")]
+struct Args {
+  /// Path of the machine code (or assembly) file to be executed
+  input: String,
+  #[command(flatten)]
+  cache: CacheArgs,
+  /// Treat the input as LC3100 assembly and assemble it before running (detected
+  /// automatically for `.as` files or input that starts with an opcode)
+  #[arg(short, long)]
+  assembly: bool,
+  /// Print the full machine state before every instruction and once the machine halts
+  #[arg(long)]
+  print_state: bool,
+  /// Print cache statistics once the machine halts
+  #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+  stats: Option<StatsFormat>,
+  /// What happens when an `add` overflows
+  #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+  overflow: OverflowPolicy,
+  /// Write a record of every executed instruction (PC, word, registers written, memory
+  /// accessed and cache hits) to this file
+  #[arg(long, value_name = "FILE")]
+  trace: Option<String>,
+  /// The format of the `--trace` file
+  #[arg(long, value_enum, default_value_t = TraceFormat::Jsonl, requires = "trace")]
+  trace_format: TraceFormat,
+  /// Write every address the L1 caches are accessed with to this file in Dinero's `din` format
+  /// (byte addresses, 4 bytes per word)
+  #[arg(long, value_name = "FILE")]
+  din: Option<String>,
+  /// Save the whole machine (registers, memory and every cache) to this file when it halts,
+  /// fails or reaches `--snapshot-after`
+  #[arg(long, value_name = "FILE")]
+  snapshot: Option<String>,
+  /// Stop once this many instructions have been executed in all (counting those run before a
+  /// `--restore`) and save the snapshot
+  #[arg(long, value_name = "N", requires = "snapshot")]
+  snapshot_after: Option<usize>,
+  /// Resume from a snapshot saved with `--snapshot`, the cache arguments must be the same as
+  /// when it was saved
+  #[arg(long, value_name = "FILE")]
+  restore: Option<String>,
+}
+
+fn main() {
   // Read in command line argument using CLAP package for simplicity
-  let args = Args::parse();
-  if !is_power_of_two(args.block_size_in_words)
-    || !is_power_of_two(args.number_of_sets)
-    || !is_power_of_two(args.blocks_per_set)
-  {
-    eprintln!("[ERROR]: The cache arguments must be a power of 2");
-    return Err(1);
-  }
-
-  let input_path = Path::new(&args.input);
-  let file = match File::open(&input_path) {
-    Err(why) => panic!("Couldn't open {}: {}", input_path.display(), why),
-    Ok(file) => file,
+  let cli = Cli::parse();
+  let result = match cli.command {
+    Some(Command::Assemble(args)) => assemble_file(args),
+    Some(Command::Disassemble(args)) => disassemble_file(args),
+    Some(Command::Debug(args)) => debug(*args),
+    Some(Command::CacheTrace(args)) => cache_trace(*args),
+    Some(Command::Pipeline(args)) => pipeline(*args),
+    Some(Command::BranchPredict(args)) => branch_predict(*args),
+    Some(Command::Multicycle(args)) => multicycle(*args),
+    // Clap requires the simulator arguments whenever no subcommand is given
+    None => simulate(cli.run.expect("simulator arguments are required")),
   };
 
-  let mut state = State {
-    pc: 0,
-    mem: [0; NUM_MEMORY],
-    reg: [0; NUM_REGISTER],
-    num_memory: 0,
+  if let Err(error) = result {
+    eprintln!("[ERROR]: {}", error);
+    std::process::exit(error.exit_code());
+  }
+}
+
+fn assemble_file(args: AssembleArgs) -> Result<(), SimError> {
+  let source = fs::read_to_string(&args.input).map_err(|why| io_error(&args.input, why))?;
+  let program = assembler::assemble(&source)?;
+
+  match args.output {
+    Some(output) => {
+      fs::write(&output, program.to_machine_code()).map_err(|why| io_error(&output, why))?
+    }
+    None => print!("{}", program.to_machine_code()),
+  }
+  Ok(())
+}
+
+fn disassemble_file(args: DisassembleArgs) -> Result<(), SimError> {
+  let source = fs::read_to_string(&args.input).map_err(|why| io_error(&args.input, why))?;
+  let program = parse_machine_code(&source)?;
+  let assembly = disassemble(&program.words);
+
+  match args.output {
+    Some(output) => fs::write(&output, assembly).map_err(|why| io_error(&output, why))?,
+    None => print!("{}", assembly),
+  }
+  Ok(())
+}
+
+impl CacheArgs {
+  /// Builds the caches from L1 down, along with the separate instruction cache if there is one
+  fn build(&self) -> Result<(Vec<Cache>, Option<Cache>), SimError> {
+    let l1 = CacheConfig {
+      replacement: self.replacement,
+      seed: self.seed,
+      write_policy: self.write,
+      write_miss_policy: self.write_miss,
+      write_buffer_size: self.write_buffer,
+      hit_latency: self.hit_latency,
+      ..CacheConfig::new(
+        self.block_size_in_words,
+        self.number_of_sets,
+        self.blocks_per_set,
+      )
+    };
+    let levels: Vec<CacheConfig> = [Some(l1), self.l2.clone(), self.l3.clone()]
+      .into_iter()
+      .flatten()
+      .collect();
+    for level in levels.iter().chain(self.icache.iter()) {
+      level.validate()?;
+    }
+
+    let caches = levels.iter().map(Cache::from_config).collect();
+    Ok((caches, self.icache.as_ref().map(Cache::from_config)))
+  }
+
+  fn memory_timing(&self) -> MemoryTiming {
+    MemoryTiming {
+      latency: self.memory_latency,
+      word_cycles: self.word_cycles,
+    }
+  }
+}
+
+/// Builds the caches described by the arguments and loads the program into a new machine,
+/// restoring a snapshot over it if asked to
+fn build_machine(args: &Args) -> Result<Machine, SimError> {
+  let mut machine = load_machine(&args.input, args.assembly, &args.cache, args.overflow)?;
+  if let Some(path) = &args.restore {
+    restore_snapshot(&mut machine, path)?;
+  }
+  Ok(machine)
+}
+
+/// Builds the caches described by `cache` and loads the program at `input` into a new machine
+fn load_machine(
+  input: &str,
+  assembly: bool,
+  cache: &CacheArgs,
+  overflow: OverflowPolicy,
+) -> Result<Machine, SimError> {
+  let (memory, program) = build_memory(input, assembly, cache)?;
+  let mut machine = Machine::with_memory(memory);
+  machine.set_overflow_policy(overflow);
+  machine.load(&program)?;
+  Ok(machine)
+}
+
+/// Builds the caches described by `cache` in front of main memory and reads the program at
+/// `input`, ready to be loaded into any of the models
+fn build_memory(
+  input: &str,
+  assembly: bool,
+  cache: &CacheArgs,
+) -> Result<(MemorySystem, Program), SimError> {
+  let (caches, instruction_cache) = cache.build()?;
+  let program = load_program(Path::new(input), assembly)?;
+
+  let mut memory = MemorySystem::new(caches, instruction_cache);
+  memory.set_memory_timing(cache.memory_timing());
+  if cache.geometry {
+    for cache in memory.all_caches() {
+      print_geometry(cache);
+    }
+  }
+  Ok((memory, program))
+}
+
+fn simulate(args: Args) -> Result<(), SimError> {
+  let mut machine = build_machine(&args)?;
+  let header = match args.trace_format {
+    TraceFormat::Csv => Some(CSV_HEADER),
+    TraceFormat::Jsonl => None,
+  };
+  let mut trace = match &args.trace {
+    Some(path) => Some(open_trace(path, header)?),
+    None => None,
+  };
+  let mut din = match &args.din {
+    Some(path) => Some(open_trace(path, None)?),
+    None => None,
   };
+  while !machine.is_halted() && args.snapshot_after != Some(machine.instructions_executed()) {
+    if args.print_state {
+      print_state(&machine);
+    }
+    let result = machine.step();
+    if let (Some((path, writer)), Some(record)) = (trace.as_mut(), machine.last_step()) {
+      writeln!(writer, "{}", record.format(args.trace_format))
+        .map_err(|why| io_error(path, why))?;
+    }
+    if let (Some((path, writer)), Some(record)) = (din.as_mut(), machine.last_step()) {
+      for (kind, address) in record.accesses() {
+        writeln!(writer, "{}", din_line(kind, address)).map_err(|why| io_error(path, why))?;
+      }
+    }
+    if let (Err(_), Some(path)) = (&result, &args.snapshot) {
+      save_snapshot(&machine, path)?;
+    }
+    result?;
+  }
+  for (path, mut writer) in trace.into_iter().chain(din) {
+    writer.flush().map_err(|why| io_error(&path, why))?;
+  }
+  if let Some(path) = &args.snapshot {
+    save_snapshot(&machine, path)?;
+  }
 
-  let mut cache = Cache::new(
-    args.block_size_in_words,
-    args.number_of_sets,
-    args.blocks_per_set,
+  if machine.is_halted() {
+    println!("\nmachine halted");
+  } else {
+    println!("\nmachine stopped, snapshot saved");
+  }
+  println!(
+    "total of {} instructions executed",
+    machine.instructions_executed()
   );
+  if args.print_state {
+    println!("final state of the machine:");
+    print_state(&machine);
+  }
+  if args.cache.timing {
+    print_timing(&machine.timing());
+  }
+  if let Some(format) = args.stats {
+    print_all_stats(&machine.memory_system().all_caches(), format);
+  }
+  Ok(())
+}
 
-  // Read instructions from file into memory
-  for line in io::BufReader::new(file).lines().enumerate() {
-    let instruction: i32 = line.1.unwrap().trim().parse().expect("NaN");
-    state.mem[line.0] = instruction;
-    state.num_memory += 1;
-  }
-
-  let mut halted = false;
-  let mut count = 0;
-
-  while !halted && state.pc as usize <= NUM_MEMORY {
-    // print_state(&state);
-
-    let current_instruction = cache.get_value(state.pc as usize, &mut state.mem);
-    state.pc += 1;
-
-    let current_instruction = parse_instruction(current_instruction);
-    match current_instruction {
-      OpType::O(o_type) => match o_type.code {
-        OTypeOpcode::Halt => {
-          halted = true;
-          // Not sure if writebacks are necessary for dirty blocks on halt,
-          // if not just comment out the line below.
-          cache.writeback_dirty_blocks(&mut state.mem);
-        }
-        OTypeOpcode::NoOp => continue,
-        // unused so treating as NoOp
-        OTypeOpcode::X => continue,
-      },
-      OpType::R(r_type) => match r_type.code {
-        RTypeOpcode::Add => {
-          let reg_a = state.reg[r_type.register_a as usize];
-          let reg_b = state.reg[r_type.register_b as usize];
-          state.reg[r_type.destination as usize] = reg_a + reg_b;
-        }
-        RTypeOpcode::Nand => {
-          let reg_a = state.reg[r_type.register_a as usize];
-          let reg_b = state.reg[r_type.register_b as usize];
-          state.reg[r_type.destination as usize] = !(reg_a & reg_b);
-        }
-      },
-      OpType::I(i_type) => match i_type.code {
-        ITypeOpcode::LoadWord => {
-          let reg_a = state.reg[i_type.register_a as usize];
-          let address = (i_type.offset as i32 + reg_a) as usize;
-          state.reg[i_type.register_b as usize] = cache.get_value(address, &mut state.mem);
-        }
-        ITypeOpcode::StoreWord => {
-          let address = state.reg[i_type.register_a as usize] + i_type.offset as i32;
-          let value = state.reg[i_type.register_b as usize];
-          cache.set_value(address as usize, &mut state.mem, value);
-        }
-        ITypeOpcode::BranchEq => {
-          let reg_a = state.reg[i_type.register_a as usize];
-          let reg_b = state.reg[i_type.register_b as usize];
-          if reg_a == reg_b {
-            state.pc = (state.pc as i32 + i_type.offset as i32) as u32;
-          }
-        }
-      },
-    }
+fn pipeline(args: ModelArgs) -> Result<(), SimError> {
+  let (memory, program) = build_memory(&args.input, args.assembly, &args.cache)?;
+  let mut machine = PipelineMachine::with_memory(memory);
+  machine.set_overflow_policy(args.overflow);
+  machine.load(&program)?;
 
-    count += 1;
+  while !machine.is_halted() {
+    print_pipeline(&machine);
+    machine.step()?;
   }
 
   println!("\nmachine halted");
-  println!("total of {} instructions executed", count);
-  // println!("final state of the machine:");
-  // print_state(&state);
+  print_pipeline_stats(machine.stats());
+  print_memory_summary(machine.memory_system(), &args);
+  Ok(())
+}
+
+/// Prints the memory system's timing and statistics once a cycle-level model has halted. The
+/// models print their own CPI, so only the memory system's cycles and AMAT are given here.
+fn print_memory_summary(memory: &MemorySystem, args: &ModelArgs) {
+  if args.cache.timing {
+    print_timing(&memory.timing());
+  }
+  if let Some(format) = args.stats {
+    print_all_stats(&memory.all_caches(), format);
+  }
+}
+
+fn multicycle(args: ModelArgs) -> Result<(), SimError> {
+  let (memory, program) = build_memory(&args.input, args.assembly, &args.cache)?;
+  let mut machine = MulticycleMachine::with_memory(memory);
+  machine.set_overflow_policy(args.overflow);
+  machine.load(&program)?;
+
+  while !machine.is_halted() {
+    print_control_state(&machine);
+    machine.step()?;
+  }
+
+  println!("\nmachine halted");
+  print_multicycle_stats(machine.stats());
+  print_memory_summary(machine.memory_system(), &args);
+  Ok(())
+}
+
+fn branch_predict(args: BranchPredictArgs) -> Result<(), SimError> {
+  let config = PredictorConfig {
+    table_size: args.table_size,
+    history_bits: args.history_bits,
+    btb_size: args.btb,
+  };
+  config.validate()?;
+  let kinds = if args.predictor.is_empty() {
+    PredictorKind::value_variants().to_vec()
+  } else {
+    args.predictor
+  };
+  let mut study = BranchStudy::new(&kinds, &config);
+
+  let mut machine = load_machine(&args.input, args.assembly, &args.cache, args.overflow)?;
+
+  while !machine.is_halted() {
+    machine.step()?;
+    if let Some(branch) = last_branch(&machine) {
+      study.record(&branch);
+    }
+  }
+
+  println!("\nmachine halted");
+  println!(
+    "total of {} instructions executed",
+    machine.instructions_executed()
+  );
+  print_branch_study(&study);
+  if args.cache.timing {
+    print_timing(&machine.timing());
+  }
   Ok(())
 }
+
+fn cache_trace(args: CacheTraceArgs) -> Result<(), SimError> {
+  let (mut caches, mut instruction_cache) = args.cache.build()?;
+  let source = fs::read_to_string(&args.input).map_err(|why| io_error(&args.input, why))?;
+  let accesses = parse_trace(&source)?;
+
+  name_levels(instruction_cache.as_mut(), &mut caches);
+
+  let all: Vec<&Cache> = instruction_cache.iter().chain(caches.iter()).collect();
+  if args.cache.geometry {
+    for cache in all {
+      print_geometry(cache);
+    }
+  }
+
+  let mut memory = vec![0; NUM_MEMORY];
+  let mut memory_cycles = 0;
+  run_trace(
+    &accesses,
+    &mut caches,
+    instruction_cache.as_mut(),
+    &mut TimedMemory {
+      memory: &mut memory,
+      timing: args.cache.memory_timing(),
+      cycles: &mut memory_cycles,
+    },
+  );
+
+  println!("\ntrace finished");
+  println!("total of {} accesses", accesses.len());
+  let all: Vec<&Cache> = instruction_cache.iter().chain(caches.iter()).collect();
+  if args.cache.timing {
+    print_timing(&TimingSummary::new(
+      &all,
+      memory_cycles,
+      accesses.len(),
+      None,
+    ));
+  }
+  print_all_stats(&all, args.stats);
+  Ok(())
+}
+
+fn restore_snapshot(machine: &mut Machine, path: &str) -> Result<(), SimError> {
+  let text = fs::read_to_string(path).map_err(|why| io_error(path, why))?;
+  machine.restore(Snapshot::parse(&text)?)
+}
+
+fn save_snapshot(machine: &Machine, path: &str) -> Result<(), SimError> {
+  fs::write(path, machine.snapshot().to_text()).map_err(|why| io_error(path, why))
+}
+
+/// Creates a trace file, starting it with `header` if there is one
+fn open_trace(path: &str, header: Option<&str>) -> Result<(String, BufWriter<fs::File>), SimError> {
+  let file = fs::File::create(path).map_err(|why| io_error(path, why))?;
+  let mut writer = BufWriter::new(file);
+  if let Some(header) = header {
+    writeln!(writer, "{}", header).map_err(|why| io_error(path, why))?;
+  }
+  Ok((path.to_string(), writer))
+}
+
+/// An error reading or writing the file at `path`
+fn io_error(path: &str, why: io::Error) -> SimError {
+  SimError::Io {
+    path: path.to_string(),
+    message: why.to_string(),
+  }
+}
+
+fn debug(args: DebugArgs) -> Result<(), SimError> {
+  let mut machine = load_machine(&args.input, args.assembly, &args.cache, args.overflow)?;
+  if let Some(path) = &args.restore {
+    restore_snapshot(&mut machine, path)?;
+  }
+  println!("type `help` for a list of commands");
+  Debugger::new(machine)
+    .repl(io::stdin().lock())
+    .map_err(|why| io_error("stdin", why))
+}
+
+fn print_all_stats(caches: &[&Cache], format: StatsFormat) {
+  match format {
+    StatsFormat::Text => {
+      for cache in caches {
+        print_stats(cache);
+      }
+    }
+    StatsFormat::Json => println!("{}", stats_json(caches)),
+  }
+}
+
+/// A single cache's statistics as JSON, or an object keyed by cache name when there are more
+fn stats_json(caches: &[&Cache]) -> String {
+  if let [cache] = caches {
+    return cache.stats().to_json();
+  }
+
+  let levels: Vec<String> = caches
+    .iter()
+    .map(|cache| format!("\"{}\":{}", cache.name(), cache.stats().to_json()))
+    .collect();
+  format!("{{{}}}", levels.join(","))
+}
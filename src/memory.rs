@@ -0,0 +1,432 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! The levels of the memory hierarchy a cache can sit in front of. Main memory is the bottom
+//! level, and a [`Hierarchy`] chains caches on top of it (L1 -> L2 -> ... -> memory).
+
+use crate::assembler::Program;
+use crate::cache::{AccessKind, Cache, CacheSnapshot, CacheUndo};
+use crate::error::SimError;
+use crate::timing::{MemoryTiming, TimedMemory, TimingSummary};
+use crate::NUM_MEMORY;
+
+/// A level of the memory hierarchy that a cache reads blocks from and writes blocks to
+pub trait MemoryLevel {
+  /// Name used for the level in transfer messages, e.g. "memory" or "L2 cache"
+  fn name(&self) -> &str;
+  /// Fills `data` with the words starting at `address`
+  fn read_block(&mut self, address: usize, data: &mut [i32], kind: AccessKind);
+  /// Writes `data` to the words starting at `address`
+  fn write_block(&mut self, address: usize, data: &[i32]);
+}
+
+/// Main memory
+impl MemoryLevel for Vec<i32> {
+  fn name(&self) -> &str {
+    "memory"
+  }
+
+  fn read_block(&mut self, address: usize, data: &mut [i32], _kind: AccessKind) {
+    data.copy_from_slice(&self[address..address + data.len()]);
+  }
+
+  fn write_block(&mut self, address: usize, data: &[i32]) {
+    self[address..address + data.len()].copy_from_slice(data);
+  }
+}
+
+/// Caches ordered from the highest level down, with main memory below the last one. Reads and
+/// writes go to the first cache, which uses the rest of the hierarchy as its next level.
+pub struct Hierarchy<'a> {
+  pub caches: &'a mut [Cache],
+  pub memory: &'a mut dyn MemoryLevel,
+}
+
+impl Hierarchy<'_> {
+  /// Flushes every cache from the top down, so main memory ends up holding every store
+  pub fn flush(&mut self) {
+    for idx in 0..self.caches.len() {
+      let (cache, lower) = self.caches[idx..].split_first_mut().unwrap();
+      cache.flush(&mut Hierarchy {
+        caches: lower,
+        memory: &mut *self.memory,
+      });
+    }
+  }
+}
+
+impl MemoryLevel for Hierarchy<'_> {
+  fn name(&self) -> &str {
+    match self.caches.first() {
+      Some(cache) => cache.name(),
+      None => self.memory.name(),
+    }
+  }
+
+  fn read_block(&mut self, address: usize, data: &mut [i32], kind: AccessKind) {
+    match self.caches.split_first_mut() {
+      Some((cache, lower)) => cache.read_block(
+        address,
+        data,
+        kind,
+        &mut Hierarchy {
+          caches: lower,
+          memory: &mut *self.memory,
+        },
+      ),
+      None => self.memory.read_block(address, data, kind),
+    }
+  }
+
+  fn write_block(&mut self, address: usize, data: &[i32]) {
+    match self.caches.split_first_mut() {
+      Some((cache, lower)) => cache.write_block(
+        address,
+        data,
+        &mut Hierarchy {
+          caches: lower,
+          memory: &mut *self.memory,
+        },
+      ),
+      None => self.memory.write_block(address, data),
+    }
+  }
+}
+
+/// Names the caches of a hierarchy for transfer messages and statistics. `caches` are ordered
+/// from L1 down; with more than one level they are named "L1 cache", "L2 cache" and so on. A
+/// separate instruction cache makes the L1 the "data cache" and the two become the "instruction
+/// cache" and "data cache", with an "L1" prefix if there are lower levels.
+pub fn name_levels(instruction_cache: Option<&mut Cache>, caches: &mut [Cache]) {
+  match instruction_cache {
+    Some(instruction_cache) => {
+      let prefix = if caches.len() > 1 { "L1 " } else { "" };
+      instruction_cache.set_name(&format!("{}instruction cache", prefix));
+      caches[0].set_name(&format!("{}data cache", prefix));
+    }
+    None if caches.len() > 1 => caches[0].set_name("L1 cache"),
+    None => {}
+  }
+  for (idx, cache) in caches.iter_mut().enumerate().skip(1) {
+    cache.set_name(&format!("L{} cache", idx + 1));
+  }
+}
+
+/// Main memory and the caches in front of it, shared by [`crate::Machine`] and the cycle-level
+/// models of the datapath in [`crate::pipeline`] and [`crate::multicycle`]. Transfers to and
+/// from main memory are timed, see [`crate::timing`].
+pub struct MemorySystem {
+  mem: Vec<i32>,
+  num_memory: usize,
+  // Ordered from L1 down, the L1 is only used for data when there is an instruction cache
+  caches: Vec<Cache>,
+  // Separate L1 cache for instruction fetches, sharing the levels below the L1 data cache
+  instruction_cache: Option<Cache>,
+  timing: MemoryTiming,
+  // Cycles main memory has spent on transfers
+  memory_cycles: usize,
+  // Changes since `start_undo`, main memory is written through a `RecordingMemory` meanwhile
+  undo: Option<MemoryUndo>,
+}
+
+/// Everything needed to undo the accesses made since `MemorySystem::start_undo`
+pub(crate) struct MemoryUndo {
+  memory_cycles: usize,
+  // Words of main memory that were overwritten (through cache write-backs), oldest first
+  memory: Vec<(usize, Vec<i32>)>,
+  caches: Vec<CacheUndo>,
+  instruction_cache: Option<CacheUndo>,
+}
+
+impl MemorySystem {
+  /// Puts `caches` (ordered from L1 down) in front of main memory, with a separate L1
+  /// instruction cache sharing the levels below the L1 data cache if there is one. The caches
+  /// are named with `name_levels`.
+  pub fn new(mut caches: Vec<Cache>, mut instruction_cache: Option<Cache>) -> MemorySystem {
+    assert!(!caches.is_empty(), "a machine needs at least one cache");
+    name_levels(instruction_cache.as_mut(), &mut caches);
+    MemorySystem {
+      mem: vec![0; NUM_MEMORY],
+      num_memory: 0,
+      caches,
+      instruction_cache,
+      timing: MemoryTiming::default(),
+      memory_cycles: 0,
+      undo: None,
+    }
+  }
+
+  /// Changes how long main memory takes for each transfer from now on
+  pub fn set_memory_timing(&mut self, timing: MemoryTiming) {
+    self.timing = timing;
+  }
+
+  /// Copies the program into memory starting at address 0
+  pub fn load(&mut self, program: &Program) -> Result<(), SimError> {
+    if program.words.len() > NUM_MEMORY {
+      return Err(SimError::ProgramTooLarge {
+        words: program.words.len(),
+      });
+    }
+    self.mem[..program.words.len()].copy_from_slice(&program.words);
+    self.num_memory = self.num_memory.max(program.words.len());
+    Ok(())
+  }
+
+  /// Reads the instruction at `pc` through the instruction cache, or the L1 cache if
+  /// instructions don't have their own
+  pub fn fetch(&mut self, pc: usize) -> i32 {
+    self.with_l1(|cache, instruction_cache, memory| {
+      instruction_cache
+        .unwrap_or(cache)
+        .get_value(pc, memory, AccessKind::InstructionFetch)
+    })
+  }
+
+  /// Reads the word at `address` for a `lw`
+  pub fn load_word(&mut self, address: usize) -> i32 {
+    self.with_l1(|cache, _, memory| cache.get_value(address, memory, AccessKind::Load))
+  }
+
+  /// Writes `value` to `address` for a `sw`
+  pub fn store_word(&mut self, address: usize, value: i32) {
+    self.with_l1(|cache, _, memory| cache.set_value(address, memory, value))
+  }
+
+  /// Writes every dirty block back down to main memory, for when the machine halts
+  pub fn flush(&mut self) {
+    self.with_l1(|cache, _, memory| {
+      cache.flush(memory);
+      memory.flush();
+    })
+  }
+
+  /// Runs an access on the L1 caches, with the lower levels and timed main memory below them.
+  /// Main memory remembers what it overwrites while changes are being recorded for `undo`.
+  fn with_l1<T>(
+    &mut self,
+    access: impl FnOnce(&mut Cache, Option<&mut Cache>, &mut Hierarchy) -> T,
+  ) -> T {
+    let mut recording;
+    let main_memory: &mut dyn MemoryLevel = match self.undo.as_mut() {
+      Some(undo) => {
+        recording = RecordingMemory {
+          memory: &mut self.mem,
+          log: &mut undo.memory,
+        };
+        &mut recording
+      }
+      None => &mut self.mem,
+    };
+    let mut timed_memory = TimedMemory {
+      memory: main_memory,
+      timing: self.timing,
+      cycles: &mut self.memory_cycles,
+    };
+    let (cache, lower) = self.caches.split_first_mut().unwrap();
+    let mut memory = Hierarchy {
+      caches: lower,
+      memory: &mut timed_memory,
+    };
+    access(cache, self.instruction_cache.as_mut(), &mut memory)
+  }
+
+  /// Starts remembering every change to the caches and main memory, so the accesses made until
+  /// `finish_undo` can be undone
+  pub(crate) fn start_undo(&mut self) {
+    for cache in self
+      .caches
+      .iter_mut()
+      .chain(self.instruction_cache.iter_mut())
+    {
+      cache.start_undo();
+    }
+    self.undo = Some(MemoryUndo {
+      memory_cycles: self.memory_cycles,
+      memory: Vec::new(),
+      caches: Vec::new(),
+      instruction_cache: None,
+    });
+  }
+
+  /// Stops remembering changes, returning what's needed to undo everything since `start_undo`
+  pub(crate) fn finish_undo(&mut self) -> Option<MemoryUndo> {
+    let mut undo = self.undo.take()?;
+    undo.caches = self
+      .caches
+      .iter_mut()
+      .filter_map(Cache::finish_undo)
+      .collect();
+    undo.instruction_cache = self.instruction_cache.as_mut().and_then(Cache::finish_undo);
+    Some(undo)
+  }
+
+  /// Puts main memory and every cache back the way they were when `start_undo` was called
+  pub(crate) fn undo(&mut self, undo: MemoryUndo) {
+    self.memory_cycles = undo.memory_cycles;
+    for (address, words) in undo.memory.into_iter().rev() {
+      self.mem[address..address + words.len()].copy_from_slice(&words);
+    }
+    for (cache, cache_undo) in self.caches.iter_mut().zip(undo.caches) {
+      cache.undo(cache_undo);
+    }
+    if let (Some(cache), Some(cache_undo)) =
+      (self.instruction_cache.as_mut(), undo.instruction_cache)
+    {
+      cache.undo(cache_undo);
+    }
+  }
+
+  /// Puts main memory and the caches back the way a snapshot saved them, see
+  /// `Machine::restore`. Nothing is changed if the snapshot doesn't fit this memory system.
+  pub(crate) fn restore(
+    &mut self,
+    memory: Vec<i32>,
+    program_length: usize,
+    memory_cycles: usize,
+    caches: Vec<(String, CacheSnapshot)>,
+  ) -> Result<(), SimError> {
+    if memory.len() != NUM_MEMORY {
+      return Err(SimError::InvalidSnapshot(format!(
+        "The snapshot has {} words of memory but the machine has {}",
+        memory.len(),
+        NUM_MEMORY
+      )));
+    }
+    if program_length > NUM_MEMORY {
+      return Err(SimError::InvalidSnapshot(format!(
+        "The snapshot's program is {} words long, which does not fit in memory",
+        program_length
+      )));
+    }
+    let names: Vec<&str> = self.all_caches().into_iter().map(Cache::name).collect();
+    let saved: Vec<&str> = caches.iter().map(|(name, _)| name.as_str()).collect();
+    if names != saved {
+      return Err(SimError::InvalidCache(format!(
+        "The snapshot has the caches [{}] but the machine has [{}]",
+        saved.join(", "),
+        names.join(", ")
+      )));
+    }
+    // Check every cache before changing any of them
+    for (cache, (_, saved)) in self.all_caches().into_iter().zip(caches.iter()) {
+      cache.check_snapshot(saved)?;
+    }
+
+    let all_caches = self
+      .instruction_cache
+      .iter_mut()
+      .chain(self.caches.iter_mut());
+    for (cache, (_, saved)) in all_caches.zip(caches) {
+      cache.restore(saved)?;
+    }
+    self.mem = memory;
+    self.num_memory = program_length;
+    self.memory_cycles = memory_cycles;
+    Ok(())
+  }
+
+  /// Memory as seen by main memory, dirty blocks still in the cache are not reflected
+  /// until they are written back (which happens on halt)
+  pub fn memory(&self) -> &[i32] {
+    &self.mem
+  }
+
+  /// The word at `address` as the processor sees it, taken from the highest level cache that
+  /// has its block. Does not affect the caches. An instruction cache is never written to, so it
+  /// can't hold anything newer than the levels below it and isn't checked.
+  pub fn peek_memory(&self, address: usize) -> i32 {
+    self
+      .caches
+      .iter()
+      .find_map(|cache| cache.peek(address))
+      .unwrap_or(self.mem[address])
+  }
+
+  /// Changes the word at `address` in memory and in every cache holding it, without going
+  /// through the caches as an access
+  pub fn poke_memory(&mut self, address: usize, value: i32) {
+    self.mem[address] = value;
+    for cache in self
+      .caches
+      .iter_mut()
+      .chain(self.instruction_cache.iter_mut())
+    {
+      cache.poke(address, value);
+    }
+  }
+
+  /// The number of words the loaded program occupies
+  pub fn num_memory(&self) -> usize {
+    self.num_memory
+  }
+
+  /// The L1 cache, or the L1 data cache when instructions have their own cache
+  pub fn cache(&self) -> &Cache {
+    &self.caches[0]
+  }
+
+  /// Every level of the cache hierarchy data accesses go through, from L1 down
+  pub fn caches(&self) -> &[Cache] {
+    &self.caches
+  }
+
+  /// The separate L1 instruction cache, if there is one
+  pub fn instruction_cache(&self) -> Option<&Cache> {
+    self.instruction_cache.as_ref()
+  }
+
+  /// The cache instructions are fetched through, the L1 cache if they don't have their own
+  pub fn fetch_cache(&self) -> &Cache {
+    self.instruction_cache.as_ref().unwrap_or(&self.caches[0])
+  }
+
+  /// Every cache, the instruction cache first if there is one
+  pub fn all_caches(&self) -> Vec<&Cache> {
+    self
+      .instruction_cache
+      .iter()
+      .chain(self.caches.iter())
+      .collect()
+  }
+
+  /// Cycles main memory has spent on transfers so far
+  pub fn memory_cycles(&self) -> usize {
+    self.memory_cycles
+  }
+
+  /// The cycles spent in the caches and main memory so far, see `Machine::timing`. The memory
+  /// system doesn't know how many instructions were run, so there is no CPI.
+  pub fn timing(&self) -> TimingSummary {
+    // Every fetch, load and store goes to one of the L1 caches
+    let accesses = self
+      .instruction_cache
+      .iter()
+      .chain(self.caches.first())
+      .map(|cache| cache.stats().reads() + cache.stats().writes())
+      .sum();
+    TimingSummary::new(&self.all_caches(), self.memory_cycles, accesses, None)
+  }
+}
+
+/// Main memory that remembers the old value of every word written, so the writes can be undone
+pub(crate) struct RecordingMemory<'a> {
+  pub memory: &'a mut Vec<i32>,
+  // The address of each write with the words it replaced, oldest first
+  pub log: &'a mut Vec<(usize, Vec<i32>)>,
+}
+
+impl MemoryLevel for RecordingMemory<'_> {
+  fn name(&self) -> &str {
+    self.memory.name()
+  }
+
+  fn read_block(&mut self, address: usize, data: &mut [i32], kind: AccessKind) {
+    self.memory.read_block(address, data, kind);
+  }
+
+  fn write_block(&mut self, address: usize, data: &[i32]) {
+    let old = self.memory[address..address + data.len()].to_vec();
+    self.log.push((address, old));
+    self.memory.write_block(address, data);
+  }
+}
@@ -0,0 +1,330 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! A multicycle LC3100, where a control finite state machine takes each instruction through
+//! one state per cycle instead of executing it in a single step.
+//!
+//! Every instruction starts with fetch (the instruction register is loaded and the PC
+//! incremented) and decode (regA and regB are read into the A and B registers), then goes
+//! through the states for its class:
+//!
+//! | Instruction | States after decode | Cycles |
+//! | --- | --- | --- |
+//! | `add`, `nand` | ALU, ALU writeback | 4 |
+//! | `lw` | address, memory read, load writeback | 5 |
+//! | `sw` | address, memory write | 4 |
+//! | `beq` | branch | 3 |
+//! | `jalr` | jump | 3 |
+//! | `halt` | halt | 3 |
+//! | `noop` | | 2 |
+//!
+//! Fetches and loads and stores go through the same caches as the single-cycle machine, and
+//! each state takes one cycle whether the cache hits or not. What the accesses would cost in
+//! the memory system is kept separately, see [`MemorySystem::timing`].
+
+use crate::assembler::Program;
+use crate::cache::Cache;
+use crate::error::SimError;
+use crate::machine::{data_address, jump_target};
+use crate::memory::MemorySystem;
+use crate::parser::parse_instruction;
+use crate::types::*;
+use crate::{OverflowPolicy, NUM_MEMORY, NUM_REGISTER};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The states of the control unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ControlState {
+  /// Loads the instruction register from memory and increments the PC
+  Fetch,
+  /// Reads regA and regB and picks the next state from the opcode
+  Decode,
+  /// Adds or nands A and B
+  Alu,
+  /// Writes the ALU result to the destination register
+  AluWriteback,
+  /// Adds the offset to A for `lw` and `sw`
+  Address,
+  /// Reads the word at the computed address into the memory data register
+  MemoryRead,
+  /// Writes the memory data register to regB
+  LoadWriteback,
+  /// Writes B to the computed address
+  MemoryWrite,
+  /// Compares A and B and updates the PC if they are equal
+  Branch,
+  /// Writes PC+1 to regB and jumps to A
+  Jump,
+  /// Writes back the caches and stops the machine
+  Halt,
+}
+
+impl fmt::Display for ControlState {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      ControlState::Fetch => "fetch",
+      ControlState::Decode => "decode",
+      ControlState::Alu => "alu",
+      ControlState::AluWriteback => "alu writeback",
+      ControlState::Address => "address",
+      ControlState::MemoryRead => "memory read",
+      ControlState::LoadWriteback => "load writeback",
+      ControlState::MemoryWrite => "memory write",
+      ControlState::Branch => "branch",
+      ControlState::Jump => "jump",
+      ControlState::Halt => "halt",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Counters for the cycles a multicycle machine has run
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MulticycleStats {
+  pub cycles: usize,
+  /// Instructions that finished their last state
+  pub instructions: usize,
+  /// Cycles spent in each control state
+  pub state_cycles: BTreeMap<ControlState, usize>,
+}
+
+impl MulticycleStats {
+  /// Cycles per instruction
+  pub fn cpi(&self) -> Option<f64> {
+    match self.instructions {
+      0 => None,
+      instructions => Some(self.cycles as f64 / instructions as f64),
+    }
+  }
+}
+
+pub struct MulticycleMachine {
+  pc: u32,
+  reg: [i32; NUM_REGISTER],
+  memory: MemorySystem,
+  overflow: OverflowPolicy,
+  // The datapath's internal registers, kept between states
+  control: ControlState,
+  instruction_register: i32,
+  // The address the instruction in the instruction register was fetched from
+  instruction_pc: u32,
+  a: i32,
+  b: i32,
+  alu_result: i32,
+  memory_data: i32,
+  halted: bool,
+  stats: MulticycleStats,
+}
+
+impl MulticycleMachine {
+  pub fn new(cache: Cache) -> MulticycleMachine {
+    MulticycleMachine::with_memory(MemorySystem::new(vec![cache], None))
+  }
+
+  /// Creates a machine in front of `memory`, fetch uses its instruction cache if it has one
+  pub fn with_memory(memory: MemorySystem) -> MulticycleMachine {
+    MulticycleMachine {
+      pc: 0,
+      reg: [0; NUM_REGISTER],
+      memory,
+      overflow: OverflowPolicy::Wrap,
+      control: ControlState::Fetch,
+      instruction_register: 0,
+      instruction_pc: 0,
+      a: 0,
+      b: 0,
+      alu_result: 0,
+      memory_data: 0,
+      halted: false,
+      stats: MulticycleStats::default(),
+    }
+  }
+
+  pub fn set_overflow_policy(&mut self, overflow: OverflowPolicy) {
+    self.overflow = overflow;
+  }
+
+  /// Copies the program into memory starting at address 0
+  pub fn load(&mut self, program: &Program) -> Result<(), SimError> {
+    self.memory.load(program)
+  }
+
+  /// Runs the current control state for one cycle and moves to the next. Returns false without
+  /// doing anything if the machine has already halted.
+  pub fn step(&mut self) -> Result<bool, SimError> {
+    if self.halted {
+      return Ok(false);
+    }
+    let state = self.control;
+    let instruction = parse_instruction(self.instruction_register);
+
+    let next = match state {
+      ControlState::Fetch => {
+        if self.pc as usize >= NUM_MEMORY {
+          return Err(SimError::PcOutOfRange { pc: self.pc as i64 });
+        }
+        self.instruction_register = self.memory.fetch(self.pc as usize);
+        self.instruction_pc = self.pc;
+        self.pc += 1;
+        ControlState::Decode
+      }
+      ControlState::Decode => {
+        let (register_a, register_b) = match instruction {
+          OpType::R(r_type) => (r_type.register_a, r_type.register_b),
+          OpType::I(i_type) => (i_type.register_a, i_type.register_b),
+          OpType::J(j_type) => (j_type.register_a, j_type.register_b),
+          OpType::O(_) => (0, 0),
+        };
+        self.a = self.reg[register_a as usize];
+        self.b = self.reg[register_b as usize];
+        match instruction {
+          OpType::R(_) => ControlState::Alu,
+          OpType::I(i_type) => match i_type.code {
+            ITypeOpcode::LoadWord | ITypeOpcode::StoreWord => ControlState::Address,
+            ITypeOpcode::BranchEq => ControlState::Branch,
+          },
+          OpType::J(_) => ControlState::Jump,
+          OpType::O(o_type) => match o_type.code {
+            OTypeOpcode::Halt => ControlState::Halt,
+            OTypeOpcode::NoOp => self.retire(),
+          },
+        }
+      }
+      ControlState::Alu => {
+        if let OpType::R(r_type) = instruction {
+          self.alu_result = match r_type.code {
+            RTypeOpcode::Add => match self.overflow {
+              OverflowPolicy::Wrap => self.a.wrapping_add(self.b),
+              OverflowPolicy::Trap => {
+                self
+                  .a
+                  .checked_add(self.b)
+                  .ok_or(SimError::ArithmeticOverflow {
+                    pc: self.instruction_pc,
+                    reg_a: self.a,
+                    reg_b: self.b,
+                  })?
+              }
+            },
+            RTypeOpcode::Nand => !(self.a & self.b),
+          };
+        }
+        ControlState::AluWriteback
+      }
+      ControlState::AluWriteback => {
+        if let OpType::R(r_type) = instruction {
+          self.reg[r_type.destination as usize] = self.alu_result;
+        }
+        self.retire()
+      }
+      ControlState::Address => {
+        let OpType::I(i_type) = instruction else {
+          unreachable!("only lw and sw compute an address")
+        };
+        let address = data_address(self.a, i_type.offset, self.instruction_pc)?;
+        self.alu_result = address as i32;
+        match i_type.code {
+          ITypeOpcode::StoreWord => ControlState::MemoryWrite,
+          _ => ControlState::MemoryRead,
+        }
+      }
+      ControlState::MemoryRead => {
+        self.memory_data = self.memory.load_word(self.alu_result as usize);
+        ControlState::LoadWriteback
+      }
+      ControlState::LoadWriteback => {
+        if let OpType::I(i_type) = instruction {
+          self.reg[i_type.register_b as usize] = self.memory_data;
+        }
+        self.retire()
+      }
+      ControlState::MemoryWrite => {
+        self.memory.store_word(self.alu_result as usize, self.b);
+        self.retire()
+      }
+      ControlState::Branch => {
+        if let OpType::I(i_type) = instruction {
+          if self.a == self.b {
+            self.pc = jump_target(self.pc as i64 + i_type.offset as i64)?;
+          }
+        }
+        self.retire()
+      }
+      ControlState::Jump => {
+        if let OpType::J(j_type) = instruction {
+          let return_address = self.pc;
+          // regB is written before regA is read, so using the same register jumps to PC+1
+          let target = if j_type.register_a == j_type.register_b {
+            return_address as i64
+          } else {
+            self.a as i64
+          };
+          // The target is checked first so a bad jump leaves regB as it was
+          self.pc = jump_target(target)?;
+          self.reg[j_type.register_b as usize] = return_address as i32;
+        }
+        self.retire()
+      }
+      ControlState::Halt => {
+        self.halted = true;
+        self.memory.flush();
+        self.retire()
+      }
+    };
+
+    self.stats.cycles += 1;
+    *self.stats.state_cycles.entry(state).or_default() += 1;
+    self.control = next;
+    Ok(true)
+  }
+
+  /// Finishes the instruction in the instruction register, going back to fetch the next one
+  fn retire(&mut self) -> ControlState {
+    self.stats.instructions += 1;
+    ControlState::Fetch
+  }
+
+  /// Runs until the machine halts, returning the number of cycles taken
+  pub fn run(&mut self) -> Result<usize, SimError> {
+    while self.step()? {}
+    Ok(self.stats.cycles)
+  }
+
+  pub fn pc(&self) -> u32 {
+    self.pc
+  }
+
+  pub fn registers(&self) -> &[i32] {
+    &self.reg
+  }
+
+  pub fn register(&self, index: usize) -> i32 {
+    self.reg[index]
+  }
+
+  /// Main memory and the caches
+  pub fn memory_system(&self) -> &MemorySystem {
+    &self.memory
+  }
+
+  /// The state the control unit runs next cycle
+  pub fn control_state(&self) -> ControlState {
+    self.control
+  }
+
+  /// The instruction being executed (the last one fetched) and the address it came from
+  pub fn current_instruction(&self) -> (u32, OpType) {
+    (
+      self.instruction_pc,
+      parse_instruction(self.instruction_register),
+    )
+  }
+
+  pub fn stats(&self) -> &MulticycleStats {
+    &self.stats
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+}
@@ -3,66 +3,104 @@
 use crate::types::*;
 
 pub fn parse_instruction(instruction: i32) -> OpType {
-    match get_opcode(instruction) {
-        0b000 => OpType::R(RType {
-            code: RTypeOpcode::Add,
-            register_a: get_reg_a(instruction),
-            register_b: get_reg_b(instruction),
-            destination: get_dest_reg(instruction),
-        }),
-        0b001 => OpType::R(RType {
-            code: RTypeOpcode::Nand,
-            register_a: get_reg_a(instruction),
-            register_b: get_reg_b(instruction),
-            destination: get_dest_reg(instruction),
-        }),
-        0b010 => OpType::I(IType {
-            code: ITypeOpcode::LoadWord,
-            register_a: get_reg_a(instruction),
-            register_b: get_reg_b(instruction),
-            offset: get_offset(instruction),
-        }),
-        0b011 => OpType::I(IType {
-            code: ITypeOpcode::StoreWord,
-            register_a: get_reg_a(instruction),
-            register_b: get_reg_b(instruction),
-            offset: get_offset(instruction),
-        }),
-        0b100 => OpType::I(IType {
-            code: ITypeOpcode::BranchEq,
-            register_a: get_reg_a(instruction),
-            register_b: get_reg_b(instruction),
-            offset: get_offset(instruction),
-        }),
-        0b101 => OpType::O(OType {
-            code: OTypeOpcode::X,
-        }),
-        0b110 => OpType::O(OType {
-            code: OTypeOpcode::Halt,
-        }),
-        0b111 => OpType::O(OType {
-            code: OTypeOpcode::NoOp,
-        }),
-        _ => panic!("Something went wrong when calculating the opcode"),
+  match get_opcode(instruction) {
+    0b000 => OpType::R(RType {
+      code: RTypeOpcode::Add,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+      destination: get_dest_reg(instruction),
+    }),
+    0b001 => OpType::R(RType {
+      code: RTypeOpcode::Nand,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+      destination: get_dest_reg(instruction),
+    }),
+    0b010 => OpType::I(IType {
+      code: ITypeOpcode::LoadWord,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+      offset: get_offset(instruction),
+    }),
+    0b011 => OpType::I(IType {
+      code: ITypeOpcode::StoreWord,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+      offset: get_offset(instruction),
+    }),
+    0b100 => OpType::I(IType {
+      code: ITypeOpcode::BranchEq,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+      offset: get_offset(instruction),
+    }),
+    0b101 => OpType::J(JType {
+      code: JTypeOpcode::JumpAndLink,
+      register_a: get_reg_a(instruction),
+      register_b: get_reg_b(instruction),
+    }),
+    0b110 => OpType::O(OType {
+      code: OTypeOpcode::Halt,
+    }),
+    0b111 => OpType::O(OType {
+      code: OTypeOpcode::NoOp,
+    }),
+    // get_opcode masks the opcode to three bits, so every value is covered above
+    _ => unreachable!("opcode is masked to three bits"),
+  }
+}
+
+/// The inverse of `parse_instruction`: encodes an instruction back into its machine code word.
+/// Bits an instruction doesn't use are left as zero, so `encode(&parse_instruction(word))` only
+/// gives back `word` when those bits were zero to begin with.
+pub fn encode(instruction: &OpType) -> i32 {
+  match instruction {
+    OpType::R(r_type) => {
+      let opcode = match r_type.code {
+        RTypeOpcode::Add => 0b000,
+        RTypeOpcode::Nand => 0b001,
+      };
+      encode_fields(opcode, r_type.register_a, r_type.register_b)
+        | (r_type.destination as i32 & 0b111)
+    }
+    OpType::I(i_type) => {
+      let opcode = match i_type.code {
+        ITypeOpcode::LoadWord => 0b010,
+        ITypeOpcode::StoreWord => 0b011,
+        ITypeOpcode::BranchEq => 0b100,
+      };
+      encode_fields(opcode, i_type.register_a, i_type.register_b) | (i_type.offset as i32 & 0xFFFF)
     }
+    OpType::J(j_type) => match j_type.code {
+      JTypeOpcode::JumpAndLink => encode_fields(0b101, j_type.register_a, j_type.register_b),
+    },
+    OpType::O(o_type) => match o_type.code {
+      OTypeOpcode::Halt => 0b110 << 22,
+      OTypeOpcode::NoOp => 0b111 << 22,
+    },
+  }
+}
+
+fn encode_fields(opcode: i32, register_a: u8, register_b: u8) -> i32 {
+  (opcode << 22) | ((register_a as i32 & 0b111) << 19) | ((register_b as i32 & 0b111) << 16)
 }
 
 fn get_opcode(instruction: i32) -> u8 {
-    ((instruction >> 22) & 0b111) as u8
+  ((instruction >> 22) & 0b111) as u8
 }
 
 fn get_reg_a(instruction: i32) -> u8 {
-    ((instruction >> 19) & 0b111) as u8
+  ((instruction >> 19) & 0b111) as u8
 }
 
 fn get_reg_b(instruction: i32) -> u8 {
-    ((instruction >> 16) & 0b111) as u8
+  ((instruction >> 16) & 0b111) as u8
 }
 
 fn get_dest_reg(instruction: i32) -> u8 {
-    (instruction & 0b111) as u8
+  (instruction & 0b111) as u8
 }
 
 fn get_offset(instruction: i32) -> i16 {
-    (instruction & 0xFFFF) as i16
+  (instruction & 0xFFFF) as i16
 }
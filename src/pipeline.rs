@@ -0,0 +1,443 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! A five-stage pipelined LC3100 (IF, ID, EX, MEM, WB), as an alternative to the single-cycle
+//! [`Machine`](crate::Machine).
+//!
+//! Instructions move through the IF/ID, ID/EX, EX/MEM and MEM/WB pipeline registers, one stage
+//! per cycle. The register file is written in the first half of a cycle and read in the second,
+//! so an instruction in ID sees what WB writes in the same cycle. Results still in EX/MEM or
+//! MEM/WB are forwarded to EX. A `lw` followed directly by an instruction using the loaded
+//! register stalls for one cycle, as the word isn't available until the end of MEM.
+//!
+//! Branches are predicted not taken: fetching carries on after a `beq`, and if it is taken (or
+//! the instruction is a `jalr`) the three instructions fetched after it are squashed once it
+//! resolves in MEM. Fetches and data accesses go through the same caches as the single-cycle
+//! machine, but a miss doesn't stall the pipeline. The machine halts when a `halt` reaches WB.
+
+use crate::assembler::Program;
+use crate::cache::Cache;
+use crate::error::SimError;
+use crate::machine::{data_address, jump_target};
+use crate::memory::MemorySystem;
+use crate::parser::parse_instruction;
+use crate::types::*;
+use crate::{OverflowPolicy, NUM_MEMORY, NUM_REGISTER};
+
+/// An instruction in the pipeline, along with the address it was fetched from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fetched {
+  pub pc: u32,
+  pub word: i32,
+}
+
+impl Fetched {
+  pub fn decode(&self) -> OpType {
+    parse_instruction(self.word)
+  }
+}
+
+// A pipeline register holding `None` as its instruction is a bubble
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IfId {
+  pub instruction: Option<Fetched>,
+  pub pc_plus_1: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IdEx {
+  pub instruction: Option<Fetched>,
+  pub pc_plus_1: u32,
+  pub read_a: i32,
+  pub read_b: i32,
+  pub offset: i32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExMem {
+  pub instruction: Option<Fetched>,
+  /// The result of `add` or `nand`, or the return address a `jalr` writes
+  pub alu_result: i32,
+  /// Where a `beq` or `jalr` goes
+  pub target: i64,
+  /// Whether the registers a `beq` compares are equal
+  pub equal: bool,
+  /// The (forwarded) value of regA, which `lw` and `sw` add their offset to
+  pub read_a: i32,
+  /// The (forwarded) value of regB, the word `sw` stores
+  pub read_b: i32,
+  /// The operands of an `add` that overflowed, only an error once the `add` is known to
+  /// complete
+  pub overflow: Option<(i32, i32)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemWb {
+  pub instruction: Option<Fetched>,
+  pub write_data: i32,
+}
+
+/// The pipeline registers between each pair of stages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pipeline {
+  pub if_id: IfId,
+  pub id_ex: IdEx,
+  pub ex_mem: ExMem,
+  pub mem_wb: MemWb,
+}
+
+/// Counters for what happened to the instructions going through the pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+  pub cycles: usize,
+  /// Instructions that made it through WB
+  pub instructions: usize,
+  /// Cycles lost to load-use stalls
+  pub stalls: usize,
+  /// Taken branches and `jalr`, each one a misprediction
+  pub mispredictions: usize,
+  /// Instructions thrown away after a misprediction
+  pub squashed: usize,
+}
+
+impl PipelineStats {
+  /// Cycles per instruction
+  pub fn cpi(&self) -> Option<f64> {
+    match self.instructions {
+      0 => None,
+      instructions => Some(self.cycles as f64 / instructions as f64),
+    }
+  }
+}
+
+pub struct PipelineMachine {
+  pc: u32,
+  reg: [i32; NUM_REGISTER],
+  memory: MemorySystem,
+  stages: Pipeline,
+  overflow: OverflowPolicy,
+  halted: bool,
+  // Set when the PC has run off the end of memory, an error unless a branch takes it back
+  fetch_fault: Option<u32>,
+  stats: PipelineStats,
+}
+
+impl PipelineMachine {
+  pub fn new(cache: Cache) -> PipelineMachine {
+    PipelineMachine::with_memory(MemorySystem::new(vec![cache], None))
+  }
+
+  /// Creates a pipeline in front of `memory`, IF uses its instruction cache if it has one
+  pub fn with_memory(memory: MemorySystem) -> PipelineMachine {
+    PipelineMachine {
+      pc: 0,
+      reg: [0; NUM_REGISTER],
+      memory,
+      stages: Pipeline::default(),
+      overflow: OverflowPolicy::Wrap,
+      halted: false,
+      fetch_fault: None,
+      stats: PipelineStats::default(),
+    }
+  }
+
+  pub fn set_overflow_policy(&mut self, overflow: OverflowPolicy) {
+    self.overflow = overflow;
+  }
+
+  /// Copies the program into memory starting at address 0
+  pub fn load(&mut self, program: &Program) -> Result<(), SimError> {
+    self.memory.load(program)
+  }
+
+  /// Runs one clock cycle, moving every instruction one stage along. Returns false without
+  /// doing anything if the machine has already halted.
+  pub fn step(&mut self) -> Result<bool, SimError> {
+    if self.halted {
+      return Ok(false);
+    }
+    let old = self.stages;
+    let mut new = Pipeline::default();
+    self.stats.cycles += 1;
+
+    // WB
+    if let Some(instruction) = old.mem_wb.instruction {
+      self.stats.instructions += 1;
+      let op = instruction.decode();
+      if let Some(destination) = destination(&op) {
+        self.reg[destination] = old.mem_wb.write_data;
+      }
+      if let OpType::O(OType {
+        code: OTypeOpcode::Halt,
+      }) = op
+      {
+        self.halted = true;
+        self.memory.flush();
+        self.stages = new;
+        return Ok(true);
+      }
+    }
+
+    // MEM
+    let mut redirect = None;
+    new.mem_wb.instruction = old.ex_mem.instruction;
+    if let Some(instruction) = old.ex_mem.instruction {
+      let ex_mem = old.ex_mem;
+      new.mem_wb.write_data = ex_mem.alu_result;
+      match instruction.decode() {
+        OpType::R(_) => {
+          if let (Some((reg_a, reg_b)), OverflowPolicy::Trap) = (ex_mem.overflow, self.overflow) {
+            return Err(SimError::ArithmeticOverflow {
+              pc: instruction.pc,
+              reg_a,
+              reg_b,
+            });
+          }
+        }
+        OpType::I(i_type) => match i_type.code {
+          ITypeOpcode::LoadWord => {
+            let address = data_address(ex_mem.read_a, i_type.offset, instruction.pc)?;
+            new.mem_wb.write_data = self.memory.load_word(address);
+          }
+          ITypeOpcode::StoreWord => {
+            let address = data_address(ex_mem.read_a, i_type.offset, instruction.pc)?;
+            self.memory.store_word(address, ex_mem.read_b);
+          }
+          ITypeOpcode::BranchEq => {
+            if ex_mem.equal {
+              redirect = Some(ex_mem.target);
+            }
+          }
+        },
+        OpType::J(_) => redirect = Some(ex_mem.target),
+        OpType::O(_) => {}
+      }
+    }
+
+    // EX
+    new.ex_mem.instruction = old.id_ex.instruction;
+    if let Some(instruction) = old.id_ex.instruction {
+      new.ex_mem = execute(instruction, &old);
+    }
+
+    // ID, holding the instruction in IF/ID back a cycle if it needs a word still being loaded
+    let stall = match (old.id_ex.instruction, old.if_id.instruction) {
+      (Some(load), Some(user)) => match load.decode() {
+        OpType::I(IType {
+          code: ITypeOpcode::LoadWord,
+          register_b,
+          ..
+        }) => sources(&user.decode()).contains(&Some(register_b as usize)),
+        _ => false,
+      },
+      _ => false,
+    };
+    if stall {
+      self.stats.stalls += 1;
+      new.if_id = old.if_id;
+    } else {
+      new.id_ex = decode(old.if_id, &self.reg);
+
+      // IF
+      let pc = self.pc;
+      if (pc as usize) < NUM_MEMORY {
+        let word = self.memory.fetch(pc as usize);
+        new.if_id = IfId {
+          instruction: Some(Fetched { pc, word }),
+          pc_plus_1: pc + 1,
+        };
+        self.pc += 1;
+      } else {
+        self.fetch_fault = Some(pc);
+      }
+    }
+
+    // Branches were predicted not taken, so everything fetched since is on the wrong path
+    if let Some(target) = redirect {
+      self.stats.mispredictions += 1;
+      self.stats.squashed += [
+        new.if_id.instruction,
+        new.id_ex.instruction,
+        new.ex_mem.instruction,
+      ]
+      .iter()
+      .flatten()
+      .count();
+      new.if_id = IfId::default();
+      new.id_ex = IdEx::default();
+      new.ex_mem = ExMem::default();
+      self.pc = jump_target(target)?;
+      self.fetch_fault = None;
+    }
+
+    self.stages = new;
+    // Nothing left in the pipeline could take the PC back inside memory
+    if let Some(pc) = self.fetch_fault {
+      if new == Pipeline::default() {
+        return Err(SimError::PcOutOfRange { pc: pc as i64 });
+      }
+    }
+    Ok(true)
+  }
+
+  /// Runs until the machine halts, returning the number of cycles taken
+  pub fn run(&mut self) -> Result<usize, SimError> {
+    while self.step()? {}
+    Ok(self.stats.cycles)
+  }
+
+  pub fn pc(&self) -> u32 {
+    self.pc
+  }
+
+  pub fn registers(&self) -> &[i32] {
+    &self.reg
+  }
+
+  pub fn register(&self, index: usize) -> i32 {
+    self.reg[index]
+  }
+
+  /// Main memory and the caches
+  pub fn memory_system(&self) -> &MemorySystem {
+    &self.memory
+  }
+
+  pub fn stages(&self) -> &Pipeline {
+    &self.stages
+  }
+
+  pub fn stats(&self) -> &PipelineStats {
+    &self.stats
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+}
+
+/// Reads the registers an instruction uses into ID/EX
+fn decode(if_id: IfId, reg: &[i32; NUM_REGISTER]) -> IdEx {
+  let Some(instruction) = if_id.instruction else {
+    return IdEx::default();
+  };
+  let (register_a, register_b, offset) = match instruction.decode() {
+    OpType::R(r_type) => (r_type.register_a, r_type.register_b, 0),
+    OpType::I(i_type) => (i_type.register_a, i_type.register_b, i_type.offset as i32),
+    OpType::J(j_type) => (j_type.register_a, j_type.register_b, 0),
+    OpType::O(_) => (0, 0, 0),
+  };
+  IdEx {
+    instruction: Some(instruction),
+    pc_plus_1: if_id.pc_plus_1,
+    read_a: reg[register_a as usize],
+    read_b: reg[register_b as usize],
+    offset,
+  }
+}
+
+/// EX for the instruction in ID/EX, with its registers forwarded from the instructions ahead
+fn execute(instruction: Fetched, old: &Pipeline) -> ExMem {
+  let id_ex = old.id_ex;
+  let op = instruction.decode();
+  let [source_a, source_b] = sources(&op);
+  let read_a = forward(source_a, id_ex.read_a, old);
+  let read_b = forward(source_b, id_ex.read_b, old);
+
+  let mut ex_mem = ExMem {
+    instruction: Some(instruction),
+    read_a,
+    read_b,
+    ..ExMem::default()
+  };
+  match op {
+    OpType::R(r_type) => match r_type.code {
+      RTypeOpcode::Add => {
+        ex_mem.alu_result = read_a.wrapping_add(read_b);
+        if read_a.checked_add(read_b).is_none() {
+          ex_mem.overflow = Some((read_a, read_b));
+        }
+      }
+      RTypeOpcode::Nand => ex_mem.alu_result = !(read_a & read_b),
+    },
+    OpType::I(i_type) => match i_type.code {
+      ITypeOpcode::LoadWord | ITypeOpcode::StoreWord => {}
+      ITypeOpcode::BranchEq => {
+        ex_mem.target = id_ex.pc_plus_1 as i64 + id_ex.offset as i64;
+        ex_mem.equal = read_a == read_b;
+      }
+    },
+    OpType::J(j_type) => {
+      ex_mem.alu_result = id_ex.pc_plus_1 as i32;
+      // regB is written before regA is read, so using the same register jumps to PC+1
+      ex_mem.target = if j_type.register_a == j_type.register_b {
+        id_ex.pc_plus_1 as i64
+      } else {
+        read_a as i64
+      };
+    }
+    OpType::O(_) => {}
+  }
+  ex_mem
+}
+
+/// The newest value of a register for the instruction in EX, taken from the instructions in
+/// EX/MEM and MEM/WB if they write it
+fn forward(register: Option<usize>, read: i32, old: &Pipeline) -> i32 {
+  let Some(register) = register else {
+    return read;
+  };
+  if let Some(instruction) = old.ex_mem.instruction {
+    let op = instruction.decode();
+    // A load's word isn't ready until MEM is done, the stall in ID makes sure it isn't needed
+    let is_load = matches!(
+      op,
+      OpType::I(IType {
+        code: ITypeOpcode::LoadWord,
+        ..
+      })
+    );
+    if !is_load && destination(&op) == Some(register) {
+      return old.ex_mem.alu_result;
+    }
+  }
+  if let Some(instruction) = old.mem_wb.instruction {
+    if destination(&instruction.decode()) == Some(register) {
+      return old.mem_wb.write_data;
+    }
+  }
+  read
+}
+
+/// The register an instruction writes
+fn destination(op: &OpType) -> Option<usize> {
+  match op {
+    OpType::R(r_type) => Some(r_type.destination as usize),
+    OpType::I(IType {
+      code: ITypeOpcode::LoadWord,
+      register_b,
+      ..
+    }) => Some(*register_b as usize),
+    OpType::J(j_type) => Some(j_type.register_b as usize),
+    _ => None,
+  }
+}
+
+/// The registers an instruction reads as regA and regB
+fn sources(op: &OpType) -> [Option<usize>; 2] {
+  match op {
+    OpType::R(r_type) => [
+      Some(r_type.register_a as usize),
+      Some(r_type.register_b as usize),
+    ],
+    OpType::I(i_type) => match i_type.code {
+      ITypeOpcode::LoadWord => [Some(i_type.register_a as usize), None],
+      ITypeOpcode::StoreWord | ITypeOpcode::BranchEq => [
+        Some(i_type.register_a as usize),
+        Some(i_type.register_b as usize),
+      ],
+    },
+    OpType::J(j_type) => [Some(j_type.register_a as usize), None],
+    OpType::O(_) => [None, None],
+  }
+}
@@ -0,0 +1,402 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Branch predictors, for studying how well each would have predicted the `beq` instructions
+//! of a program.
+//!
+//! The machine itself always resolves branches straight away, so predictors are run alongside
+//! it: after every `beq` each predictor is asked what it would have guessed and is then told
+//! what actually happened. Predictors that use a table index it with the low bits of the PC
+//! (gshare XORs in the global history first). With a branch target buffer (BTB), a branch
+//! predicted taken only counts as taken if the BTB also has its target, as a fetch stage
+//! wouldn't know where to go otherwise.
+
+use crate::error::SimError;
+use crate::types::*;
+use crate::utils::is_power_of_two;
+use crate::Machine;
+use std::collections::BTreeMap;
+
+/// A `beq` that was executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Branch {
+  pub pc: u32,
+  /// Where the branch goes when it is taken
+  pub target: i64,
+  pub taken: bool,
+}
+
+/// The branch executed by the machine's last step, if it was a `beq`
+pub fn last_branch(machine: &Machine) -> Option<Branch> {
+  let record = machine.last_step()?;
+  match record.instruction {
+    OpType::I(IType {
+      code: ITypeOpcode::BranchEq,
+      register_a,
+      register_b,
+      offset,
+    }) => Some(Branch {
+      pc: record.pc,
+      target: record.pc as i64 + 1 + offset as i64,
+      // `beq` doesn't write any registers, so they still hold what it compared
+      taken: machine.register(register_a as usize) == machine.register(register_b as usize),
+    }),
+    _ => None,
+  }
+}
+
+/// Guesses whether branches are taken, learning from how they actually went
+pub trait BranchPredictor {
+  /// Whether the branch at `pc` going to `target` is predicted to be taken
+  fn predict(&self, pc: u32, target: i64) -> bool;
+  /// Called with the outcome of every branch after it was predicted
+  fn update(&mut self, branch: &Branch);
+}
+
+/// The predictors that can be selected from the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum PredictorKind {
+  /// Every branch falls through
+  NotTaken,
+  /// Every branch is taken
+  Taken,
+  /// Backward branches (loops) are taken, forward ones fall through
+  Btfn,
+  /// A table of bits remembering whether each branch was last taken
+  OneBit,
+  /// A table of 2-bit saturating counters
+  TwoBit,
+  /// 2-bit counters indexed by the PC XORed with the global branch history
+  Gshare,
+}
+
+impl PredictorKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      PredictorKind::NotTaken => "not-taken",
+      PredictorKind::Taken => "taken",
+      PredictorKind::Btfn => "btfn",
+      PredictorKind::OneBit => "one-bit",
+      PredictorKind::TwoBit => "two-bit",
+      PredictorKind::Gshare => "gshare",
+    }
+  }
+}
+
+/// The sizes of the predictors' tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PredictorConfig {
+  /// Entries in the 1-bit, 2-bit and gshare tables
+  pub table_size: usize,
+  /// Branches remembered in gshare's global history
+  pub history_bits: usize,
+  /// Entries in the branch target buffer, None for no BTB
+  pub btb_size: Option<usize>,
+}
+
+impl Default for PredictorConfig {
+  fn default() -> PredictorConfig {
+    PredictorConfig {
+      table_size: 16,
+      history_bits: 4,
+      btb_size: None,
+    }
+  }
+}
+
+impl PredictorConfig {
+  /// Checks the tables can be indexed by the low bits of the PC
+  pub fn validate(&self) -> Result<(), SimError> {
+    if !is_power_of_two(self.table_size) {
+      return Err(SimError::InvalidPredictor(format!(
+        "The predictor table size ({}) must be a power of 2",
+        self.table_size
+      )));
+    }
+    if self.history_bits > 16 {
+      return Err(SimError::InvalidPredictor(format!(
+        "The global history ({} bits) can't be longer than a PC (16 bits)",
+        self.history_bits
+      )));
+    }
+    if let Some(btb_size) = self.btb_size {
+      if !is_power_of_two(btb_size) {
+        return Err(SimError::InvalidPredictor(format!(
+          "The BTB size ({}) must be a power of 2",
+          btb_size
+        )));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Creates a predictor with its tables sized by `config`
+pub fn new_predictor(kind: PredictorKind, config: &PredictorConfig) -> Box<dyn BranchPredictor> {
+  match kind {
+    PredictorKind::NotTaken => Box::new(Static { taken: false }),
+    PredictorKind::Taken => Box::new(Static { taken: true }),
+    PredictorKind::Btfn => Box::new(Btfn),
+    PredictorKind::OneBit => Box::new(OneBit::new(config.table_size)),
+    PredictorKind::TwoBit => Box::new(TwoBit::new(config.table_size)),
+    PredictorKind::Gshare => Box::new(Gshare::new(config.table_size, config.history_bits)),
+  }
+}
+
+/// Predicts the same way for every branch
+pub struct Static {
+  taken: bool,
+}
+
+impl BranchPredictor for Static {
+  fn predict(&self, _pc: u32, _target: i64) -> bool {
+    self.taken
+  }
+
+  fn update(&mut self, _branch: &Branch) {}
+}
+
+/// Backward taken, forward not taken
+pub struct Btfn;
+
+impl BranchPredictor for Btfn {
+  fn predict(&self, pc: u32, target: i64) -> bool {
+    target <= pc as i64
+  }
+
+  fn update(&mut self, _branch: &Branch) {}
+}
+
+/// Predicts each branch goes the same way it did last time
+pub struct OneBit {
+  taken: Vec<bool>,
+}
+
+impl OneBit {
+  pub fn new(table_size: usize) -> OneBit {
+    OneBit {
+      taken: vec![false; table_size],
+    }
+  }
+}
+
+impl BranchPredictor for OneBit {
+  fn predict(&self, pc: u32, _target: i64) -> bool {
+    self.taken[pc as usize % self.taken.len()]
+  }
+
+  fn update(&mut self, branch: &Branch) {
+    let index = branch.pc as usize % self.taken.len();
+    self.taken[index] = branch.taken;
+  }
+}
+
+/// Counts from 0 (strongly not taken) to 3 (strongly taken), predicting taken from 2 up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Counter(u8);
+
+impl Counter {
+  // Weakly not taken, so a branch only has to be taken once to be predicted taken
+  const INITIAL: Counter = Counter(1);
+
+  fn predict(&self) -> bool {
+    self.0 >= 2
+  }
+
+  fn update(&mut self, taken: bool) {
+    self.0 = if taken {
+      (self.0 + 1).min(3)
+    } else {
+      self.0.saturating_sub(1)
+    };
+  }
+}
+
+/// A 2-bit saturating counter for each branch, so a loop's exit doesn't flip its prediction
+pub struct TwoBit {
+  counters: Vec<Counter>,
+}
+
+impl TwoBit {
+  pub fn new(table_size: usize) -> TwoBit {
+    TwoBit {
+      counters: vec![Counter::INITIAL; table_size],
+    }
+  }
+}
+
+impl BranchPredictor for TwoBit {
+  fn predict(&self, pc: u32, _target: i64) -> bool {
+    self.counters[pc as usize % self.counters.len()].predict()
+  }
+
+  fn update(&mut self, branch: &Branch) {
+    let index = branch.pc as usize % self.counters.len();
+    self.counters[index].update(branch.taken);
+  }
+}
+
+/// 2-bit counters shared by branches with the same PC and global history XORed together,
+/// so a branch can be predicted differently depending on how the branches before it went
+pub struct Gshare {
+  counters: Vec<Counter>,
+  // The last `history_bits` branches, the most recent in the lowest bit
+  history: usize,
+  history_bits: usize,
+}
+
+impl Gshare {
+  pub fn new(table_size: usize, history_bits: usize) -> Gshare {
+    Gshare {
+      counters: vec![Counter::INITIAL; table_size],
+      history: 0,
+      history_bits,
+    }
+  }
+
+  fn index(&self, pc: u32) -> usize {
+    (pc as usize ^ self.history) % self.counters.len()
+  }
+}
+
+impl BranchPredictor for Gshare {
+  fn predict(&self, pc: u32, _target: i64) -> bool {
+    self.counters[self.index(pc)].predict()
+  }
+
+  fn update(&mut self, branch: &Branch) {
+    let index = self.index(branch.pc);
+    self.counters[index].update(branch.taken);
+    self.history = ((self.history << 1) | branch.taken as usize) & ((1 << self.history_bits) - 1);
+  }
+}
+
+/// A direct-mapped cache of the targets of taken branches, indexed by PC
+pub struct BranchTargetBuffer {
+  entries: Vec<Option<(u32, i64)>>,
+}
+
+impl BranchTargetBuffer {
+  pub fn new(size: usize) -> BranchTargetBuffer {
+    BranchTargetBuffer {
+      entries: vec![None; size],
+    }
+  }
+
+  /// The target stored for the branch at `pc`, if it is in the buffer
+  pub fn lookup(&self, pc: u32) -> Option<i64> {
+    match self.entries[pc as usize % self.entries.len()] {
+      Some((tag, target)) if tag == pc => Some(target),
+      _ => None,
+    }
+  }
+
+  pub fn insert(&mut self, pc: u32, target: i64) {
+    let index = pc as usize % self.entries.len();
+    self.entries[index] = Some((pc, target));
+  }
+}
+
+/// How often a predictor was right, overall and for each branch
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PredictorStats {
+  pub predictions: usize,
+  pub correct: usize,
+  /// Predictions and correct predictions for each branch PC
+  pub branches: BTreeMap<u32, (usize, usize)>,
+  /// Branches predicted taken whose target was (or wasn't) in the BTB
+  pub btb_hits: usize,
+  pub btb_misses: usize,
+}
+
+impl PredictorStats {
+  pub fn accuracy(&self) -> f64 {
+    accuracy(self.correct, self.predictions)
+  }
+}
+
+/// The fraction of `predictions` that were correct, 0 when there weren't any
+pub fn accuracy(correct: usize, predictions: usize) -> f64 {
+  if predictions == 0 {
+    return 0.0;
+  }
+  correct as f64 / predictions as f64
+}
+
+/// A predictor being studied, with its own BTB if there is one
+struct Entry {
+  kind: PredictorKind,
+  predictor: Box<dyn BranchPredictor>,
+  btb: Option<BranchTargetBuffer>,
+  stats: PredictorStats,
+}
+
+/// Runs several predictors side by side against the same branches
+pub struct BranchStudy {
+  entries: Vec<Entry>,
+  /// Times each branch PC was executed and taken
+  branches: BTreeMap<u32, (usize, usize)>,
+}
+
+impl BranchStudy {
+  pub fn new(kinds: &[PredictorKind], config: &PredictorConfig) -> BranchStudy {
+    let entries = kinds
+      .iter()
+      .map(|kind| Entry {
+        kind: *kind,
+        predictor: new_predictor(*kind, config),
+        btb: config.btb_size.map(BranchTargetBuffer::new),
+        stats: PredictorStats::default(),
+      })
+      .collect();
+    BranchStudy {
+      entries,
+      branches: BTreeMap::new(),
+    }
+  }
+
+  /// Has every predictor predict `branch`, then tells them how it went
+  pub fn record(&mut self, branch: &Branch) {
+    let (executed, taken) = self.branches.entry(branch.pc).or_default();
+    *executed += 1;
+    *taken += branch.taken as usize;
+
+    for entry in self.entries.iter_mut() {
+      let mut predicted = entry.predictor.predict(branch.pc, branch.target);
+      if let (true, Some(btb)) = (predicted, entry.btb.as_mut()) {
+        // Without the target, fetch carries on past the branch as if it were not taken
+        if btb.lookup(branch.pc) == Some(branch.target) {
+          entry.stats.btb_hits += 1;
+        } else {
+          entry.stats.btb_misses += 1;
+          predicted = false;
+        }
+      }
+
+      let correct = predicted == branch.taken;
+      entry.stats.predictions += 1;
+      entry.stats.correct += correct as usize;
+      let (predictions, right) = entry.stats.branches.entry(branch.pc).or_default();
+      *predictions += 1;
+      *right += correct as usize;
+
+      entry.predictor.update(branch);
+      if let (true, Some(btb)) = (branch.taken, entry.btb.as_mut()) {
+        btb.insert(branch.pc, branch.target);
+      }
+    }
+  }
+
+  /// Times each branch PC was executed and taken
+  pub fn branches(&self) -> &BTreeMap<u32, (usize, usize)> {
+    &self.branches
+  }
+
+  /// The statistics for each predictor, in the order they were given
+  pub fn results(&self) -> impl Iterator<Item = (PredictorKind, &PredictorStats)> {
+    self.entries.iter().map(|entry| (entry.kind, &entry.stats))
+  }
+
+  pub fn has_btb(&self) -> bool {
+    self.entries.iter().any(|entry| entry.btb.is_some())
+  }
+}
@@ -0,0 +1,314 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Replacement policies decide which block of a full set gets evicted.
+//!
+//! The cache always fills invalid blocks first, a policy is only asked for a victim once
+//! every block in the set is valid. Policies keep their own bookkeeping for every set, indexed
+//! by the set index and the block's index within the set.
+
+/// Keeps track of block usage so a victim can be chosen when a set is full
+pub trait ReplacementPolicy {
+  /// Called whenever the block at `way` in `set` is accessed and hits
+  fn touch(&mut self, set: usize, way: usize);
+  /// Called whenever a new block is placed at `way` in `set`
+  fn fill(&mut self, set: usize, way: usize);
+  /// Picks the index of the block to evict from `set`, every block in the set is valid
+  fn victim(&mut self, set: usize) -> usize;
+  /// The bookkeeping for `set` (along with anything shared between sets), so it can be put
+  /// back later with `restore`
+  fn save(&self, set: usize) -> Vec<u64>;
+  /// Puts back the bookkeeping for `set` from a `save`
+  fn restore(&mut self, set: usize, state: &[u64]);
+  /// Whether `state`, of the length `save` gives, is bookkeeping the policy could be left with
+  fn accepts(&self, _set: usize, _state: &[u64]) -> bool {
+    true
+  }
+}
+
+/// The replacement policies that can be selected from the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReplacementKind {
+  /// Least recently used
+  Lru,
+  /// First in, first out
+  Fifo,
+  /// A random block, seeded so runs are repeatable
+  Random,
+  /// Tree based pseudo least recently used
+  Plru,
+  /// Least frequently used
+  Lfu,
+}
+
+/// Creates the policy for a cache with `set_count` sets of `blocks_per_set` blocks
+pub fn new_policy(
+  kind: ReplacementKind,
+  set_count: usize,
+  blocks_per_set: usize,
+  seed: u64,
+) -> Box<dyn ReplacementPolicy> {
+  match kind {
+    ReplacementKind::Lru => Box::new(Lru::new(set_count, blocks_per_set)),
+    ReplacementKind::Fifo => Box::new(Fifo::new(set_count, blocks_per_set)),
+    ReplacementKind::Random => Box::new(Random::new(blocks_per_set, seed)),
+    ReplacementKind::Plru => Box::new(TreePlru::new(set_count, blocks_per_set)),
+    ReplacementKind::Lfu => Box::new(Lfu::new(set_count, blocks_per_set)),
+  }
+}
+
+/// Evicts the block that has gone the longest without being used
+pub struct Lru {
+  // Technical detail for calculating LRU, the number of uses of other blocks
+  // in the set since each block was last used
+  instructions_since_use: Vec<Vec<usize>>,
+}
+
+impl Lru {
+  pub fn new(set_count: usize, blocks_per_set: usize) -> Lru {
+    Lru {
+      instructions_since_use: vec![vec![0; blocks_per_set]; set_count],
+    }
+  }
+}
+
+impl ReplacementPolicy for Lru {
+  // Resets the used block and increments the instructions_since_use value
+  // for every other block in the set
+  fn touch(&mut self, set: usize, way: usize) {
+    for (i, count) in self.instructions_since_use[set].iter_mut().enumerate() {
+      if i == way {
+        *count = 0;
+      } else {
+        *count += 1;
+      }
+    }
+  }
+
+  fn fill(&mut self, set: usize, way: usize) {
+    self.touch(set, way);
+  }
+
+  fn victim(&mut self, set: usize) -> usize {
+    let mut lru_idx = 0;
+    for (i, count) in self.instructions_since_use[set].iter().enumerate() {
+      if *count > self.instructions_since_use[set][lru_idx] {
+        lru_idx = i;
+      }
+    }
+    lru_idx
+  }
+
+  fn save(&self, set: usize) -> Vec<u64> {
+    self.instructions_since_use[set]
+      .iter()
+      .map(|count| *count as u64)
+      .collect()
+  }
+
+  fn restore(&mut self, set: usize, state: &[u64]) {
+    self.instructions_since_use[set] = state.iter().map(|count| *count as usize).collect();
+  }
+}
+
+/// Evicts the block that has been in the cache the longest, regardless of use
+pub struct Fifo {
+  // The fill number each block was brought in with, smaller is older
+  filled_at: Vec<Vec<u64>>,
+  fills: u64,
+}
+
+impl Fifo {
+  pub fn new(set_count: usize, blocks_per_set: usize) -> Fifo {
+    Fifo {
+      filled_at: vec![vec![0; blocks_per_set]; set_count],
+      fills: 0,
+    }
+  }
+}
+
+impl ReplacementPolicy for Fifo {
+  fn touch(&mut self, _set: usize, _way: usize) {}
+
+  fn fill(&mut self, set: usize, way: usize) {
+    self.fills += 1;
+    self.filled_at[set][way] = self.fills;
+  }
+
+  fn victim(&mut self, set: usize) -> usize {
+    let mut oldest_idx = 0;
+    for (i, filled_at) in self.filled_at[set].iter().enumerate() {
+      if *filled_at < self.filled_at[set][oldest_idx] {
+        oldest_idx = i;
+      }
+    }
+    oldest_idx
+  }
+
+  // The fill counter is shared by every set, so it's saved after the set's fill numbers
+  fn save(&self, set: usize) -> Vec<u64> {
+    let mut state = self.filled_at[set].clone();
+    state.push(self.fills);
+    state
+  }
+
+  fn restore(&mut self, set: usize, state: &[u64]) {
+    let (fills, filled_at) = state.split_last().expect("missing FIFO fill counter");
+    self.filled_at[set] = filled_at.to_vec();
+    self.fills = *fills;
+  }
+}
+
+/// Evicts a pseudo-random block, the same seed always gives the same sequence of victims
+pub struct Random {
+  blocks_per_set: usize,
+  // xorshift64 state, never zero
+  state: u64,
+}
+
+impl Random {
+  pub fn new(blocks_per_set: usize, seed: u64) -> Random {
+    // Run the seed through splitmix64 so small or zero seeds still give a good starting state
+    let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    state ^= state >> 31;
+
+    Random {
+      blocks_per_set,
+      state: if state == 0 { 1 } else { state },
+    }
+  }
+
+  fn next(&mut self) -> u64 {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 7;
+    self.state ^= self.state << 17;
+    self.state
+  }
+}
+
+impl ReplacementPolicy for Random {
+  fn touch(&mut self, _set: usize, _way: usize) {}
+
+  fn fill(&mut self, _set: usize, _way: usize) {}
+
+  fn victim(&mut self, _set: usize) -> usize {
+    (self.next() % self.blocks_per_set as u64) as usize
+  }
+
+  // Nothing is kept per set, only the generator's state
+  fn save(&self, _set: usize) -> Vec<u64> {
+    vec![self.state]
+  }
+
+  fn restore(&mut self, _set: usize, state: &[u64]) {
+    self.state = state[0];
+  }
+
+  // xorshift never leaves zero, so a generator restored to it would pick the same way forever
+  fn accepts(&self, _set: usize, state: &[u64]) -> bool {
+    state[0] != 0
+  }
+}
+
+/// Approximates LRU with a binary tree of bits per set, each bit points towards the half
+/// of its subtree that should be evicted from next. Needs a power of 2 blocks per set.
+pub struct TreePlru {
+  blocks_per_set: usize,
+  // Nodes are stored heap style, the children of node n are 2n + 1 and 2n + 2
+  bits: Vec<Vec<bool>>,
+}
+
+impl TreePlru {
+  pub fn new(set_count: usize, blocks_per_set: usize) -> TreePlru {
+    TreePlru {
+      blocks_per_set,
+      bits: vec![vec![false; blocks_per_set.saturating_sub(1)]; set_count],
+    }
+  }
+}
+
+impl ReplacementPolicy for TreePlru {
+  // Points every bit on the path to the used block away from it
+  fn touch(&mut self, set: usize, way: usize) {
+    let (mut node, mut low, mut size) = (0, 0, self.blocks_per_set);
+    while size > 1 {
+      let half = size / 2;
+      let right = way >= low + half;
+      self.bits[set][node] = !right;
+      node = 2 * node + if right { 2 } else { 1 };
+      if right {
+        low += half;
+      }
+      size = half;
+    }
+  }
+
+  fn fill(&mut self, set: usize, way: usize) {
+    self.touch(set, way);
+  }
+
+  // Follows the bits down to the block they point at
+  fn victim(&mut self, set: usize) -> usize {
+    let (mut node, mut low, mut size) = (0, 0, self.blocks_per_set);
+    while size > 1 {
+      let half = size / 2;
+      let right = self.bits[set][node];
+      node = 2 * node + if right { 2 } else { 1 };
+      if right {
+        low += half;
+      }
+      size = half;
+    }
+    low
+  }
+
+  fn save(&self, set: usize) -> Vec<u64> {
+    self.bits[set].iter().map(|bit| *bit as u64).collect()
+  }
+
+  fn restore(&mut self, set: usize, state: &[u64]) {
+    self.bits[set] = state.iter().map(|bit| *bit != 0).collect();
+  }
+}
+
+/// Evicts the block used the fewest times since it was brought in, ties go to the lowest index
+pub struct Lfu {
+  uses: Vec<Vec<usize>>,
+}
+
+impl Lfu {
+  pub fn new(set_count: usize, blocks_per_set: usize) -> Lfu {
+    Lfu {
+      uses: vec![vec![0; blocks_per_set]; set_count],
+    }
+  }
+}
+
+impl ReplacementPolicy for Lfu {
+  fn touch(&mut self, set: usize, way: usize) {
+    self.uses[set][way] += 1;
+  }
+
+  fn fill(&mut self, set: usize, way: usize) {
+    self.uses[set][way] = 1;
+  }
+
+  fn victim(&mut self, set: usize) -> usize {
+    let mut lfu_idx = 0;
+    for (i, uses) in self.uses[set].iter().enumerate() {
+      if *uses < self.uses[set][lfu_idx] {
+        lfu_idx = i;
+      }
+    }
+    lfu_idx
+  }
+
+  fn save(&self, set: usize) -> Vec<u64> {
+    self.uses[set].iter().map(|uses| *uses as u64).collect()
+  }
+
+  fn restore(&mut self, set: usize, state: &[u64]) {
+    self.uses[set] = state.iter().map(|uses| *uses as usize).collect();
+  }
+}
@@ -0,0 +1,355 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Saving a whole machine to a text file and resuming it later, see `Machine::snapshot` and
+//! `Machine::restore`.
+//!
+//! A snapshot holds the PC, registers and memory, how far the run has got, and everything in
+//! every cache: each block's valid and dirty bits, tag and data, the replacement policy's
+//! bookkeeping for each set, the write buffer and the statistics. The caches' geometry and
+//! policies aren't part of it, so a snapshot has to be restored into a machine built with the
+//! same cache options.
+//!
+//! The file is plain text, one item per line, starting with [`HEADER`]:
+//!
+//! ```text
+//! LC3100 snapshot
+//! pc 4
+//! registers 0 1 2 3 4 0 0 0
+//! halted false
+//! instructions 4
+//! memory-cycles 48
+//! program-length 16
+//! mem 0 8454154 8519691 8585228 8650765 8716302 8781839 655361 12648463 12713995 25165824 1 2 3 4 5 6
+//! cache 2.2.2 cache
+//! stats 2 2 2 2 0 0 0 0 0 8 0 0 0 0 8
+//! write-buffer
+//! set 0 policy 2 0
+//! block 1 0 0 0 8454154 8519691
+//! block 1 0 3 12 3 4
+//! set 1 policy 2 0
+//! block 1 0 2 10 1 2
+//! block 1 0 0 2 8585228 8650765
+//! end
+//! ```
+//!
+//! Memory is written in rows of [`ROW_WORDS`] words starting at the address after `mem`, rows
+//! that are all zero are left out. `block` lines are the valid bit, the dirty bit, the tag, the
+//! address of the block's first word and its data, and `write-buffer` is followed by address
+//! and value pairs. The statistics are in the same order as `CacheStats`' fields.
+
+use crate::cache::{Block, CacheSnapshot, CacheStats};
+use crate::error::SimError;
+use crate::{NUM_MEMORY, NUM_REGISTER};
+use std::str::FromStr;
+
+/// The first line of every snapshot
+pub const HEADER: &str = "LC3100 snapshot";
+/// The number of words on each `mem` line
+pub const ROW_WORDS: usize = 16;
+
+/// Everything needed to put a machine back exactly the way it was
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+  pub pc: u32,
+  pub registers: [i32; NUM_REGISTER],
+  pub halted: bool,
+  pub instructions_executed: usize,
+  /// Cycles main memory has spent on transfers, see [`crate::timing`]
+  pub memory_cycles: usize,
+  /// The number of words the loaded program occupies
+  pub program_length: usize,
+  /// Every word of main memory
+  pub memory: Vec<i32>,
+  /// Every cache by name, the instruction cache first if there is one, then from L1 down
+  pub caches: Vec<(String, CacheSnapshot)>,
+}
+
+impl Snapshot {
+  pub fn to_text(&self) -> String {
+    let mut lines = vec![
+      HEADER.to_string(),
+      format!("pc {}", self.pc),
+      format!("registers {}", join(&self.registers)),
+      format!("halted {}", self.halted),
+      format!("instructions {}", self.instructions_executed),
+      format!("memory-cycles {}", self.memory_cycles),
+      format!("program-length {}", self.program_length),
+    ];
+    for (row, words) in self.memory.chunks(ROW_WORDS).enumerate() {
+      if words.iter().any(|word| *word != 0) {
+        lines.push(format!("mem {} {}", row * ROW_WORDS, join(words)));
+      }
+    }
+
+    for (name, cache) in self.caches.iter() {
+      let (blocks, _) = &cache.sets[0];
+      lines.push(format!(
+        "cache {}.{}.{} {}",
+        blocks[0].data().len(),
+        cache.sets.len(),
+        blocks.len(),
+        name
+      ));
+      lines.push(format!("stats {}", join(&stats_fields(&cache.stats))));
+      let pending: Vec<String> = cache
+        .write_buffer
+        .iter()
+        .map(|(address, value)| format!(" {} {}", address, value))
+        .collect();
+      lines.push(format!("write-buffer{}", pending.concat()));
+      for (set_index, (blocks, policy_state)) in cache.sets.iter().enumerate() {
+        lines.push(
+          format!("set {} policy {}", set_index, join(policy_state))
+            .trim_end()
+            .to_string(),
+        );
+        for block in blocks {
+          lines.push(format!(
+            "block {} {} {} {} {}",
+            block.is_valid() as u8,
+            block.is_dirty() as u8,
+            block.tag(),
+            block.starting_address(),
+            join(block.data())
+          ));
+        }
+      }
+    }
+    lines.push("end".to_string());
+    lines.join("\n") + "\n"
+  }
+
+  /// Reads a snapshot written by `to_text`
+  pub fn parse(text: &str) -> Result<Snapshot, SimError> {
+    let mut lines = Lines {
+      lines: text.lines().enumerate(),
+      line: 0,
+    };
+    if lines.next()?.join(" ") != HEADER {
+      return Err(lines.error(format!("should be `{}`", HEADER)));
+    }
+
+    let pc = lines.field("pc")?;
+    let registers: Vec<i32> = lines.list("registers")?;
+    let registers: [i32; NUM_REGISTER] = registers
+      .try_into()
+      .map_err(|_| lines.error(format!("should have {} registers", NUM_REGISTER)))?;
+    let halted = lines.field("halted")?;
+    let instructions_executed = lines.field("instructions")?;
+    let memory_cycles = lines.field("memory-cycles")?;
+    let program_length = lines.field("program-length")?;
+    if program_length > NUM_MEMORY {
+      return Err(lines.error("is longer than memory".to_string()));
+    }
+
+    let mut memory = vec![0; NUM_MEMORY];
+    let mut caches = Vec::new();
+    loop {
+      let words = lines.next()?;
+      match words[..] {
+        ["mem", address, ..] => {
+          let address: usize = lines.parse(address)?;
+          let row: Vec<i32> = lines.parse_all(&words[2..])?;
+          if address
+            .checked_add(row.len())
+            .is_none_or(|end| end > NUM_MEMORY)
+          {
+            return Err(lines.error("runs past the end of memory".to_string()));
+          }
+          memory[address..address + row.len()].copy_from_slice(&row);
+        }
+        ["cache", geometry, ..] => {
+          let name = words[2..].join(" ");
+          caches.push((name, lines.cache(geometry)?));
+        }
+        ["end"] => break,
+        _ => return Err(lines.error("isn't part of a snapshot".to_string())),
+      }
+    }
+
+    Ok(Snapshot {
+      pc,
+      registers,
+      halted,
+      instructions_executed,
+      memory_cycles,
+      program_length,
+      memory,
+      caches,
+    })
+  }
+}
+
+/// Reads a snapshot one line at a time, keeping track of the line number for errors
+struct Lines<'a, I: Iterator<Item = (usize, &'a str)>> {
+  lines: I,
+  line: usize,
+}
+
+impl<'a, I: Iterator<Item = (usize, &'a str)>> Lines<'a, I> {
+  /// The words of the next line
+  fn next(&mut self) -> Result<Vec<&'a str>, SimError> {
+    let (index, text) = self.lines.next().ok_or_else(|| SimError::BadSnapshot {
+      line: self.line + 1,
+      message: "is missing, the snapshot ends too early".to_string(),
+    })?;
+    self.line = index + 1;
+    Ok(text.split_whitespace().collect())
+  }
+
+  fn error(&self, message: String) -> SimError {
+    SimError::BadSnapshot {
+      line: self.line,
+      message,
+    }
+  }
+
+  fn parse<T: FromStr>(&self, word: &str) -> Result<T, SimError> {
+    word
+      .parse()
+      .map_err(|_| self.error(format!("has `{}` where a number was expected", word)))
+  }
+
+  fn parse_all<T: FromStr>(&self, words: &[&str]) -> Result<Vec<T>, SimError> {
+    words.iter().map(|word| self.parse(word)).collect()
+  }
+
+  /// The values on the next line, which must start with `key`
+  fn list<T: FromStr>(&mut self, key: &str) -> Result<Vec<T>, SimError> {
+    let words = self.next()?;
+    if words.first() != Some(&key) {
+      return Err(self.error(format!("should start with `{}`", key)));
+    }
+    self.parse_all(&words[1..])
+  }
+
+  /// The single value on the next line, which must start with `key`
+  fn field<T: FromStr>(&mut self, key: &str) -> Result<T, SimError> {
+    let words = self.next()?;
+    match words[..] {
+      [first, value] if first == key => self.parse(value),
+      _ => Err(self.error(format!("should be `{}` followed by a value", key))),
+    }
+  }
+
+  /// A cache with the `B.S.A` geometry from its `cache` line
+  fn cache(&mut self, geometry: &str) -> Result<CacheSnapshot, SimError> {
+    let sizes: Vec<usize> = self.parse_all(&geometry.split('.').collect::<Vec<&str>>())?;
+    let [block_size, set_count, blocks_per_set] = sizes[..] else {
+      return Err(self.error(format!("has `{}` where B.S.A was expected", geometry)));
+    };
+
+    let fields: Vec<usize> = self.list("stats")?;
+    let stats = stats_from_fields(&fields)
+      .ok_or_else(|| self.error(format!("should have {} statistics", STATS_FIELDS)))?;
+    let pending: Vec<i64> = self.list("write-buffer")?;
+    if !pending.len().is_multiple_of(2) {
+      return Err(self.error("should have an address and a value for each write".to_string()));
+    }
+    if pending.chunks(2).any(|pair| !in_memory(pair[0])) {
+      return Err(self.error("has a write to an address outside of memory".to_string()));
+    }
+    let write_buffer = pending
+      .chunks(2)
+      .map(|pair| (pair[0] as usize, pair[1] as i32))
+      .collect();
+
+    let mut sets = Vec::new();
+    for set_index in 0..set_count {
+      let words = self.next()?;
+      let policy_state = match words[..] {
+        ["set", index, "policy", ..] if self.parse::<usize>(index)? == set_index => {
+          self.parse_all(&words[3..])?
+        }
+        _ => return Err(self.error(format!("should be `set {} policy`", set_index))),
+      };
+      let mut blocks = Vec::new();
+      for _ in 0..blocks_per_set {
+        let fields: Vec<i64> = self.list("block")?;
+        let [valid, dirty, tag, starting_address, ref data @ ..] = fields[..] else {
+          return Err(self.error("is missing the block's bits, tag or address".to_string()));
+        };
+        if data.len() != block_size {
+          return Err(self.error(format!("should have {} words of data", block_size)));
+        }
+        // The whole block has to fit in memory, and so does its tag as an address
+        if !in_memory(tag)
+          || !in_memory(starting_address)
+          || starting_address as usize + block_size > NUM_MEMORY
+        {
+          return Err(self.error("has a block outside of memory".to_string()));
+        }
+        blocks.push(Block::with_contents(
+          valid != 0,
+          dirty != 0,
+          tag as usize,
+          starting_address as usize,
+          data.iter().map(|word| *word as i32).collect(),
+        ));
+      }
+      sets.push((blocks, policy_state));
+    }
+
+    Ok(CacheSnapshot {
+      sets,
+      write_buffer,
+      stats,
+    })
+  }
+}
+
+fn in_memory(address: i64) -> bool {
+  (0..NUM_MEMORY as i64).contains(&address)
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+  let words: Vec<String> = values.iter().map(ToString::to_string).collect();
+  words.join(" ")
+}
+
+const STATS_FIELDS: usize = 15;
+
+fn stats_fields(stats: &CacheStats) -> [usize; STATS_FIELDS] {
+  [
+    stats.instruction_hits,
+    stats.instruction_misses,
+    stats.load_hits,
+    stats.load_misses,
+    stats.store_hits,
+    stats.store_misses,
+    stats.clean_evictions,
+    stats.dirty_evictions,
+    stats.halt_writebacks,
+    stats.words_from_memory,
+    stats.words_to_memory,
+    stats.write_throughs,
+    stats.write_buffer_coalesced,
+    stats.write_buffer_drains,
+    stats.access_cycles,
+  ]
+}
+
+fn stats_from_fields(fields: &[usize]) -> Option<CacheStats> {
+  let [instruction_hits, instruction_misses, load_hits, load_misses, store_hits, store_misses, clean_evictions, dirty_evictions, halt_writebacks, words_from_memory, words_to_memory, write_throughs, write_buffer_coalesced, write_buffer_drains, access_cycles] =
+    fields[..]
+  else {
+    return None;
+  };
+  Some(CacheStats {
+    instruction_hits,
+    instruction_misses,
+    load_hits,
+    load_misses,
+    store_hits,
+    store_misses,
+    clean_evictions,
+    dirty_evictions,
+    halt_writebacks,
+    words_from_memory,
+    words_to_memory,
+    write_throughs,
+    write_buffer_coalesced,
+    write_buffer_drains,
+    access_cycles,
+  })
+}
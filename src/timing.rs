@@ -0,0 +1,105 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! A simple timing model for the memory system. Every access to a cache takes its hit latency
+//! (see `CacheConfig::hit_latency`), whether it hits or misses, and every block transfer to or
+//! from main memory takes the memory latency plus a cost per word. An instruction takes as long
+//! as its fetch and its load or store, so with 1 cycle caches that always hit most instructions
+//! take 1 cycle and `lw` and `sw` take 2.
+
+use crate::cache::{AccessKind, Cache};
+use crate::memory::MemoryLevel;
+
+/// How long main memory takes to transfer a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryTiming {
+  /// Cycles before the first word of a transfer arrives
+  pub latency: usize,
+  /// Cycles for each word transferred
+  pub word_cycles: usize,
+}
+
+impl Default for MemoryTiming {
+  fn default() -> MemoryTiming {
+    MemoryTiming {
+      latency: 10,
+      word_cycles: 1,
+    }
+  }
+}
+
+impl MemoryTiming {
+  /// Cycles taken to transfer `words` words
+  pub fn transfer_cycles(&self, words: usize) -> usize {
+    self.latency + words * self.word_cycles
+  }
+}
+
+/// Main memory that adds up the cycles taken by every transfer
+pub struct TimedMemory<'a> {
+  pub memory: &'a mut dyn MemoryLevel,
+  pub timing: MemoryTiming,
+  pub cycles: &'a mut usize,
+}
+
+impl MemoryLevel for TimedMemory<'_> {
+  fn name(&self) -> &str {
+    self.memory.name()
+  }
+
+  fn read_block(&mut self, address: usize, data: &mut [i32], kind: AccessKind) {
+    *self.cycles += self.timing.transfer_cycles(data.len());
+    self.memory.read_block(address, data, kind);
+  }
+
+  fn write_block(&mut self, address: usize, data: &[i32]) {
+    *self.cycles += self.timing.transfer_cycles(data.len());
+    self.memory.write_block(address, data);
+  }
+}
+
+/// Totals from the timing model for a run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingSummary {
+  /// Cycles spent in every cache and in main memory
+  pub cycles: usize,
+  /// Cycles spent in main memory
+  pub memory_cycles: usize,
+  /// Accesses made by the processor (fetches, loads and stores)
+  pub accesses: usize,
+  /// Instructions executed, if the accesses came from running a program
+  pub instructions: Option<usize>,
+}
+
+impl TimingSummary {
+  /// Adds up the cycles spent in `caches` (every cache, at every level) and main memory
+  pub fn new(
+    caches: &[&Cache],
+    memory_cycles: usize,
+    accesses: usize,
+    instructions: Option<usize>,
+  ) -> TimingSummary {
+    let cache_cycles: usize = caches.iter().map(|cache| cache.stats().access_cycles).sum();
+    TimingSummary {
+      cycles: cache_cycles + memory_cycles,
+      memory_cycles,
+      accesses,
+      instructions,
+    }
+  }
+
+  /// Cycles per instruction
+  pub fn cpi(&self) -> Option<f64> {
+    match self.instructions {
+      Some(0) | None => None,
+      Some(instructions) => Some(self.cycles as f64 / instructions as f64),
+    }
+  }
+
+  /// Average memory access time, the cycles taken by an access from the processor on average
+  pub fn amat(&self) -> f64 {
+    if self.accesses == 0 {
+      return 0.0;
+    }
+    self.cycles as f64 / self.accesses as f64
+  }
+}
@@ -0,0 +1,151 @@
+// Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
+
+//! Machine-readable records of executed instructions, one per instruction, written as JSON
+//! Lines or CSV for tools that would otherwise have to parse the simulator's printed output.
+//! The L1 accesses each instruction makes can also be written in Dinero's `din` format to feed
+//! other cache simulators.
+
+use crate::cache::AccessKind;
+use crate::types::OpType;
+
+/// How trace records are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TraceFormat {
+  /// One JSON object per line
+  Jsonl,
+  /// Comma separated values, starting with a header line
+  Csv,
+}
+
+/// What a single executed instruction did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+  /// The instruction's position in the run, starting from 1
+  pub cycle: usize,
+  pub pc: u32,
+  /// The instruction as it was fetched from memory
+  pub word: i32,
+  pub instruction: OpType,
+  /// The register the instruction wrote and the value written to it
+  pub register_written: Option<(usize, i32)>,
+  /// The kind (load or store) and address of the data access made by `lw` or `sw`
+  pub memory_access: Option<(AccessKind, usize)>,
+  /// Whether the fetch hit in the L1 (or L1 instruction) cache
+  pub fetch_hit: bool,
+  /// Whether the data access hit in the L1 (or L1 data) cache
+  pub data_hit: Option<bool>,
+}
+
+pub const CSV_HEADER: &str =
+  "cycle,pc,word,mnemonic,instruction,register,value,access,address,fetch,data";
+
+impl TraceRecord {
+  pub fn to_json(&self) -> String {
+    let register = match self.register_written {
+      Some((register, value)) => format!("{{\"{}\":{}}}", register, value),
+      None => "{}".to_string(),
+    };
+    let (access, address) = match self.memory_access {
+      Some((kind, address)) => (format!("\"{}\"", access_name(kind)), address.to_string()),
+      None => ("null".to_string(), "null".to_string()),
+    };
+    let data = match self.data_hit {
+      Some(hit) => format!("\"{}\"", hit_name(hit)),
+      None => "null".to_string(),
+    };
+    format!(
+      concat!(
+        "{{\"cycle\":{},\"pc\":{},\"word\":{},\"mnemonic\":\"{}\",\"instruction\":\"{}\",",
+        "\"registers_written\":{},\"access\":{},\"address\":{},\"fetch\":\"{}\",\"data\":{}}}"
+      ),
+      self.cycle,
+      self.pc,
+      self.word,
+      mnemonic(&self.instruction),
+      self.instruction,
+      register,
+      access,
+      address,
+      hit_name(self.fetch_hit),
+      data
+    )
+  }
+
+  /// A line of CSV matching `CSV_HEADER`, empty fields for anything the instruction didn't do
+  pub fn to_csv(&self) -> String {
+    let (register, value) = match self.register_written {
+      Some((register, value)) => (register.to_string(), value.to_string()),
+      None => (String::new(), String::new()),
+    };
+    let (access, address) = match self.memory_access {
+      Some((kind, address)) => (access_name(kind).to_string(), address.to_string()),
+      None => (String::new(), String::new()),
+    };
+    format!(
+      "{},{},{},{},{},{},{},{},{},{},{}",
+      self.cycle,
+      self.pc,
+      self.word,
+      mnemonic(&self.instruction),
+      self.instruction,
+      register,
+      value,
+      access,
+      address,
+      hit_name(self.fetch_hit),
+      self.data_hit.map_or("", hit_name)
+    )
+  }
+
+  /// The accesses the instruction made to the L1 caches in order, the fetch and then any load
+  /// or store
+  pub fn accesses(&self) -> impl Iterator<Item = (AccessKind, usize)> {
+    std::iter::once((AccessKind::InstructionFetch, self.pc as usize)).chain(self.memory_access)
+  }
+
+  pub fn format(&self, format: TraceFormat) -> String {
+    match format {
+      TraceFormat::Jsonl => self.to_json(),
+      TraceFormat::Csv => self.to_csv(),
+    }
+  }
+}
+
+/// The number of bytes in an LC3100 word, `din` addresses are in bytes
+pub const BYTES_PER_WORD: usize = 4;
+
+/// A line of Dinero `din` input for an access to the word at `address`: the access type (0 for
+/// a read, 1 for a write, 2 for an instruction fetch) and then the byte address in hex
+pub fn din_line(kind: AccessKind, address: usize) -> String {
+  let label = match kind {
+    AccessKind::Load => 0,
+    AccessKind::Store => 1,
+    AccessKind::InstructionFetch => 2,
+  };
+  format!("{} {:x}", label, address * BYTES_PER_WORD)
+}
+
+fn mnemonic(instruction: &OpType) -> String {
+  match instruction {
+    OpType::R(r_type) => r_type.code.to_string(),
+    OpType::I(i_type) => i_type.code.to_string(),
+    OpType::J(j_type) => j_type.code.to_string(),
+    OpType::O(o_type) => o_type.code.to_string(),
+  }
+}
+
+fn access_name(kind: AccessKind) -> &'static str {
+  match kind {
+    AccessKind::InstructionFetch => "fetch",
+    AccessKind::Load => "load",
+    AccessKind::Store => "store",
+  }
+}
+
+fn hit_name(hit: bool) -> &'static str {
+  if hit {
+    "hit"
+  } else {
+    "miss"
+  }
+}
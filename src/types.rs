@@ -1,42 +1,144 @@
 // Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
 
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OpType {
-    R(RType),
-    I(IType),
-    O(OType),
+  R(RType),
+  I(IType),
+  J(JType),
+  O(OType),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct RType {
-    pub code: RTypeOpcode,
-    pub register_a: u8,
-    pub register_b: u8,
-    pub destination: u8,
+  pub code: RTypeOpcode,
+  pub register_a: u8,
+  pub register_b: u8,
+  pub destination: u8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct IType {
-    pub code: ITypeOpcode,
-    pub register_a: u8,
-    pub register_b: u8,
-    pub offset: i16,
+  pub code: ITypeOpcode,
+  pub register_a: u8,
+  pub register_b: u8,
+  pub offset: i16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JType {
+  pub code: JTypeOpcode,
+  pub register_a: u8,
+  pub register_b: u8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct OType {
-    pub code: OTypeOpcode,
+  pub code: OTypeOpcode,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RTypeOpcode {
-    Add,
-    Nand,
+  Add,
+  Nand,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ITypeOpcode {
-    LoadWord,
-    StoreWord,
-    BranchEq,
+  LoadWord,
+  StoreWord,
+  BranchEq,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JTypeOpcode {
+  JumpAndLink,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OTypeOpcode {
-    X,
-    Halt,
-    NoOp,
+  Halt,
+  NoOp,
+}
+
+// Instructions are displayed the way the assembler reads them, e.g. `add 1 2 3` or `lw 0 1 -6`
+
+impl fmt::Display for OpType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      OpType::R(r_type) => r_type.fmt(f),
+      OpType::I(i_type) => i_type.fmt(f),
+      OpType::J(j_type) => j_type.fmt(f),
+      OpType::O(o_type) => o_type.fmt(f),
+    }
+  }
+}
+
+impl fmt::Display for RType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} {} {} {}",
+      self.code, self.register_a, self.register_b, self.destination
+    )
+  }
+}
+
+impl fmt::Display for IType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} {} {} {}",
+      self.code, self.register_a, self.register_b, self.offset
+    )
+  }
+}
+
+impl fmt::Display for JType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} {}", self.code, self.register_a, self.register_b)
+  }
+}
+
+impl fmt::Display for OType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.code.fmt(f)
+  }
+}
+
+impl fmt::Display for RTypeOpcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      RTypeOpcode::Add => "add",
+      RTypeOpcode::Nand => "nand",
+    })
+  }
+}
+
+impl fmt::Display for ITypeOpcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      ITypeOpcode::LoadWord => "lw",
+      ITypeOpcode::StoreWord => "sw",
+      ITypeOpcode::BranchEq => "beq",
+    })
+  }
+}
+
+impl fmt::Display for JTypeOpcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      JTypeOpcode::JumpAndLink => "jalr",
+    })
+  }
+}
+
+impl fmt::Display for OTypeOpcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      OTypeOpcode::Halt => "halt",
+      OTypeOpcode::NoOp => "noop",
+    })
+  }
 }
@@ -1,35 +1,233 @@
 // Justin Schilleman (jas21ba) | CDA3100 | Assignment 2
 
-use crate::cache::Action;
-use crate::State;
+use crate::cache::Cache;
+use crate::multicycle::{ControlState, MulticycleMachine, MulticycleStats};
+use crate::pipeline::{Fetched, PipelineMachine, PipelineStats};
+use crate::predictor::{accuracy, BranchStudy};
+use crate::timing::TimingSummary;
+use crate::Machine;
 
-pub fn print_state(state: &State) {
+/// Prints the machine state in the same format as the reference simulator. Memory is what main
+/// memory holds, so words still waiting in dirty cache blocks aren't shown.
+pub fn print_state(machine: &Machine) {
   println!("\n@@@");
   println!("state:");
-  println!("\tpc {}", state.pc);
+  println!("\tpc {}", machine.pc());
   println!("\tmemory:");
-  for idx in 0..state.num_memory as usize {
-    println!("\t\tmem[ {} ] {}", idx, state.mem[idx]);
+  let memory = machine.memory_system();
+  for idx in 0..memory.num_memory() {
+    println!("\t\tmem[ {} ] {}", idx, memory.memory()[idx]);
   }
   println!("\tregisters:");
-  for register in state.reg.iter().enumerate() {
+  for register in machine.registers().iter().enumerate() {
     println!("\t\treg[ {} ] {}", register.0, register.1);
   }
   println!("end state");
 }
 
-pub fn print_action(address: usize, size: usize, action: Action) {
+pub fn print_stats(cache: &Cache) {
+  let stats = cache.stats();
+  println!("\n@@@");
+  println!("{} statistics:", cache.name());
+  println!("\treads {}", stats.reads());
+  println!("\twrites {}", stats.writes());
+  println!(
+    "\tinstruction fetches: {} hits, {} misses",
+    stats.instruction_hits, stats.instruction_misses
+  );
+  println!(
+    "\tdata accesses: {} hits, {} misses",
+    stats.load_hits + stats.store_hits,
+    stats.load_misses + stats.store_misses
+  );
+  println!("\thit rate {:.2}%", stats.hit_rate() * 100.0);
+  println!(
+    "\tevictions: {} clean, {} dirty",
+    stats.clean_evictions, stats.dirty_evictions
+  );
+  println!("\twrite-backs on halt {}", stats.halt_writebacks);
+  println!("\twords from memory {}", stats.words_from_memory);
+  println!("\twords to memory {}", stats.words_to_memory);
+  println!("\twrite-throughs {}", stats.write_throughs);
+  println!(
+    "\twrite buffer: {} coalesced, {} drained",
+    stats.write_buffer_coalesced, stats.write_buffer_drains
+  );
+  println!("end statistics");
+}
+
+/// Prints the size of a cache and how it splits an address into tag, set and offset bits
+pub fn print_geometry(cache: &Cache) {
+  let (tag_bits, set_bits, offset_bits) = cache.address_split();
+  let words = cache.block_size() * cache.set_count() * cache.associativity();
+  println!(
+    "{}: {} words in {} sets of {} blocks of {} words",
+    cache.name(),
+    words,
+    cache.set_count(),
+    cache.associativity(),
+    cache.block_size()
+  );
+  println!(
+    "\taddress bits: {} tag, {} set, {} offset",
+    tag_bits, set_bits, offset_bits
+  );
+}
+
+/// Prints the totals from the timing model
+pub fn print_timing(timing: &TimingSummary) {
+  println!("timing:");
+  println!("\ttotal cycles {}", timing.cycles);
+  println!("\tcycles in memory {}", timing.memory_cycles);
+  if let Some(cpi) = timing.cpi() {
+    println!("\tCPI {:.2}", cpi);
+  }
+  println!("\tAMAT {:.2} cycles", timing.amat());
+}
+
+/// Prints the pipeline registers and the registers of a pipelined machine, the pipeline's
+/// version of `print_state`
+pub fn print_pipeline(machine: &PipelineMachine) {
+  let stages = machine.stages();
+  println!("\n@@@");
+  println!("state before cycle {}:", machine.stats().cycles + 1);
+  println!("\tpc {}", machine.pc());
+  println!("\tIF/ID {}", stage_instruction(stages.if_id.instruction));
+  println!("\tID/EX {}", stage_instruction(stages.id_ex.instruction));
+  println!("\tEX/MEM {}", stage_instruction(stages.ex_mem.instruction));
+  println!("\tMEM/WB {}", stage_instruction(stages.mem_wb.instruction));
+  println!("\tregisters:");
+  for register in machine.registers().iter().enumerate() {
+    println!("\t\treg[ {} ] {}", register.0, register.1);
+  }
+  println!("end state");
+}
+
+fn stage_instruction(instruction: Option<Fetched>) -> String {
+  match instruction {
+    Some(instruction) => format!("{} (pc {})", instruction.decode(), instruction.pc),
+    None => "bubble".to_string(),
+  }
+}
+
+/// Prints the cycle counts of a pipelined machine
+pub fn print_pipeline_stats(stats: &PipelineStats) {
+  println!("pipeline:");
+  println!("\tcycles {}", stats.cycles);
+  println!("\tinstructions {}", stats.instructions);
+  if let Some(cpi) = stats.cpi() {
+    println!("\tCPI {:.2}", cpi);
+  }
+  println!("\tload-use stalls {}", stats.stalls);
+  println!(
+    "\tmispredicted branches {}, {} instructions squashed",
+    stats.mispredictions, stats.squashed
+  );
+}
+
+/// Prints the control state a multicycle machine is about to run, along with the instruction
+/// it is working on
+pub fn print_control_state(machine: &MulticycleMachine) {
+  let cycle = machine.stats().cycles + 1;
+  match machine.control_state() {
+    ControlState::Fetch => println!("@@@ cycle {}: fetch, pc {}", cycle, machine.pc()),
+    state => {
+      let (pc, instruction) = machine.current_instruction();
+      println!(
+        "@@@ cycle {}: {}, {} (pc {})",
+        cycle, state, instruction, pc
+      );
+    }
+  }
+}
+
+/// Prints the cycle counts of a multicycle machine, overall and for each control state
+pub fn print_multicycle_stats(stats: &MulticycleStats) {
+  println!("multicycle:");
+  println!("\tcycles {}", stats.cycles);
+  println!("\tinstructions {}", stats.instructions);
+  if let Some(cpi) = stats.cpi() {
+    println!("\tCPI {:.2}", cpi);
+  }
+  println!("\tcycles in each state:");
+  for (state, cycles) in stats.state_cycles.iter() {
+    println!("\t\t{} {}", state, cycles);
+  }
+}
+
+/// Prints how often each branch was taken and how accurate each predictor was, overall and
+/// for each branch
+pub fn print_branch_study(study: &BranchStudy) {
+  println!("branches:");
+  for (pc, (executed, taken)) in study.branches() {
+    println!("\tpc {}: executed {}, taken {}", pc, executed, taken);
+  }
+  println!("predictors:");
+  for (kind, stats) in study.results() {
+    println!(
+      "\t{}: {} of {} correct ({:.2}%)",
+      kind.name(),
+      stats.correct,
+      stats.predictions,
+      stats.accuracy() * 100.0
+    );
+    for (pc, (predictions, correct)) in stats.branches.iter() {
+      println!(
+        "\t\tpc {}: {} of {} correct ({:.2}%)",
+        pc,
+        correct,
+        predictions,
+        accuracy(*correct, *predictions) * 100.0
+      );
+    }
+    if study.has_btb() {
+      println!(
+        "\t\tBTB: {} hits, {} misses",
+        stats.btb_hits, stats.btb_misses
+      );
+    }
+  }
+}
+
+/// Prints the blocks in one set of a cache, with the words of each valid block
+pub fn print_cache_set(cache: &Cache, set_index: usize) {
+  println!("{} set {}:", cache.name(), set_index);
+  for (block_index, block) in cache.blocks(set_index).iter().enumerate() {
+    if !block.is_valid() {
+      println!("\tblock {}: invalid", block_index);
+      continue;
+    }
+
+    let words: Vec<String> = block.data().iter().map(|word| word.to_string()).collect();
+    println!(
+      "\tblock {}: tag {} {} words [{}-{}] {}",
+      block_index,
+      block.tag(),
+      if block.is_dirty() { "dirty" } else { "clean" },
+      block.starting_address(),
+      block.starting_address() + block.data().len() - 1,
+      words.join(" ")
+    );
+  }
+}
+
+/// Name of the processor in transfer messages
+pub const PROCESSOR: &str = "processor";
+/// Destination in transfer messages for clean blocks that are dropped from a cache
+pub const NOWHERE: &str = "nowhere";
+
+/// Prints a transfer of the words `[address, address + size)` between two parts of the
+/// machine, named like "processor", "cache", "L2 cache" or "memory"
+pub fn print_action(address: usize, size: usize, from: &str, to: &str) {
   print!(
     "@@@ transferring word [{}-{}] ",
     address,
     address + size - 1
   );
-  match action {
-    Action::CacheToProcessor => println!("from the cache to the processor"),
-    Action::ProcessorToCache => println!("from the processor to the cache"),
-    Action::MemoryToCache => println!("from the memory to the cache"),
-    Action::CacheToMemory => println!("from the cache to the memory"),
-    Action::CacheToNowhere => println!("from the cache to nowhere"),
+  if to == NOWHERE {
+    println!("from the {} to nowhere", from);
+  } else {
+    println!("from the {} to the {}", from, to);
   }
 }
 
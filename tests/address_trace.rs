@@ -0,0 +1,82 @@
+mod common;
+
+use cda3100_lc_sim::address_trace::{parse_din, parse_trace, run_trace};
+use cda3100_lc_sim::cache::{AccessKind, Cache, CacheConfig};
+use cda3100_lc_sim::replacement::ReplacementKind;
+use cda3100_lc_sim::trace::{din_line, TraceRecord, CSV_HEADER};
+use cda3100_lc_sim::{Machine, SimError, NUM_MEMORY};
+
+/// Runs a program, returning its trace records and the machine it ran on
+fn run_program(name: &str, config: &CacheConfig) -> (Vec<TraceRecord>, Machine) {
+  let mut machine = Machine::new(Cache::from_config(config));
+  machine.load(&common::assembly(name)).unwrap();
+  (common::run_traced(&mut machine), machine)
+}
+
+/// Replaying a program's din and CSV traces gives the same statistics as running it
+#[test]
+fn replay_matches_running_the_program() {
+  for replacement in [ReplacementKind::Lru, ReplacementKind::Fifo] {
+    let config = CacheConfig {
+      replacement,
+      ..CacheConfig::new(2, 2, 2)
+    };
+    let (records, machine) = run_program("jas21ba_test3.as.2.2.2", &config);
+    let expected = machine.memory_system().cache().stats().to_json();
+
+    let din: String = records
+      .iter()
+      .flat_map(TraceRecord::accesses)
+      .map(|(kind, address)| din_line(kind, address) + "\n")
+      .collect();
+    let csv: String = std::iter::once(CSV_HEADER.to_string())
+      .chain(records.iter().map(TraceRecord::to_csv))
+      .map(|line| line + "\n")
+      .collect();
+
+    for source in [din, csv] {
+      let accesses = parse_trace(&source).unwrap();
+      let mut caches = vec![Cache::from_config(&config)];
+      let mut memory = vec![0; NUM_MEMORY];
+      run_trace(&accesses, &mut caches, None, &mut memory);
+      assert_eq!(caches[0].stats().to_json(), expected);
+    }
+  }
+}
+
+#[test]
+fn replays_an_address_sequence() {
+  // Reads of word addresses 0, 1, 4, 0, 8 and 0 then a write to 4, on a direct-mapped cache
+  // with 2 sets of 2 word blocks where 0, 4 and 8 all map to set 0
+  let accesses = parse_din("0 0\n0 4\n0 10\n0 0\n\n0 20\n0 0x0\n1 10\n").unwrap();
+  assert_eq!(accesses[2], (AccessKind::Load, 4));
+  let mut caches = vec![Cache::new(2, 2, 1)];
+  let mut memory = vec![0; NUM_MEMORY];
+  run_trace(&accesses, &mut caches, None, &mut memory);
+
+  let stats = caches[0].stats();
+  assert_eq!((stats.load_hits, stats.load_misses), (1, 5));
+  assert_eq!((stats.store_hits, stats.store_misses), (0, 1));
+  assert_eq!(stats.clean_evictions, 5);
+  assert_eq!(stats.halt_writebacks, 1);
+}
+
+#[test]
+fn rejects_bad_lines() {
+  for (source, line) in [
+    ("2 0\n3 4\n", 2),
+    ("0 zz\n", 1),
+    ("0 6\n", 1),
+    ("0\n", 1),
+    ("1 100000\n", 1),
+  ] {
+    match parse_din(source) {
+      Err(SimError::BadTrace { line: actual, .. }) => assert_eq!(actual, line, "{:?}", source),
+      other => panic!(
+        "{:?} gave {:?}",
+        source,
+        other.map(|accesses| accesses.len())
+      ),
+    }
+  }
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use cda3100_lc_sim::assembler::assemble;
+use std::fs;
+
+/// Every program under `tests/assembly/` must assemble to exactly the machine code the C
+/// assembler produced for it under `tests/machine_code/`
+#[test]
+fn matches_c_assembler_output() {
+  let names = common::fixtures("assembly");
+  assert!(!names.is_empty());
+  for name in names {
+    let source = fs::read_to_string(common::fixture("assembly", &name)).unwrap();
+    let name = name.replace(".as.", ".mc.");
+    let expected = fs::read_to_string(common::fixture("machine_code", &name)).unwrap();
+    let program = assemble(&source).unwrap();
+    assert_eq!(program.to_machine_code(), expected, "{}", name);
+  }
+}
+
+#[test]
+fn reports_c_assembler_errors() {
+  let cases = [
+    ("        add  1 2\n", "bad character in register argument"),
+    ("        add  8 1 2\n", "error: register out of range"),
+    ("        lw   0 1 8x\n", "bad character in addressField"),
+    ("        beq  0 0 x\n", "error: missing label x"),
+    (
+      "        .fill\n",
+      "error at address 0: not enough arguments",
+    ),
+    (
+      "        lw   0 1 40000\n",
+      "error: offset 40000 out of range",
+    ),
+    (
+      "a       halt\na       noop\n",
+      "error: duplicate label a at address 1",
+    ),
+    ("abcdefg halt\n", "label too long"),
+    ("1ab     halt\n", "label doesn't start with letter"),
+    (
+      "a_b     halt\n",
+      "label has character other than letters and numbers",
+    ),
+    (
+      "        mul  1 2 3\n",
+      "error: unrecognized opcode mul at address 0",
+    ),
+  ];
+
+  for (source, message) in cases {
+    match assemble(source) {
+      Ok(_) => panic!("{:?} should not assemble", source),
+      Err(error) => assert_eq!(error.to_string(), message),
+    }
+  }
+}
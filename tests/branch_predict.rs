@@ -0,0 +1,27 @@
+mod common;
+
+use std::process::Command;
+
+fn run(args: &[&str]) -> String {
+  let program = common::fixture("machine_code", "jas21ba_test3.mc.2.2.2");
+  let output = Command::new(env!("CARGO_BIN_EXE_cda3100_lc_sim"))
+    .args(args)
+    .arg(program)
+    .args(["2", "2", "2", "--timing", "--memory-latency", "10"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  String::from_utf8(output.stdout).unwrap()
+}
+
+/// The timing report follows everything else, so the last lines of both runs should match
+fn timing(output: &str) -> &str {
+  &output[output.rfind("timing:").expect("no timing report")..]
+}
+
+#[test]
+fn branch_predict_honours_the_cache_options() {
+  let simulated = run(&[]);
+  let predicted = run(&["branch-predict"]);
+  assert_eq!(timing(&predicted), timing(&simulated));
+}
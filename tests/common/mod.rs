@@ -0,0 +1,149 @@
+//! Helpers shared by the integration tests, pulled into each test file with `mod common;`
+#![allow(dead_code)]
+
+use cda3100_lc_sim::assembler::{assemble, Program};
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::loader::parse_machine_code;
+use cda3100_lc_sim::memory::MemorySystem;
+use cda3100_lc_sim::multicycle::MulticycleMachine;
+use cda3100_lc_sim::pipeline::PipelineMachine;
+use cda3100_lc_sim::trace::TraceRecord;
+use cda3100_lc_sim::Machine;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The path of a file under `tests/`, e.g. `fixture("machine_code", "jas21ba_test1.mc.2.2.2")`
+pub fn fixture(dir: &str, name: &str) -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("tests")
+    .join(dir)
+    .join(name)
+}
+
+/// The name of every file under `tests/<dir>/`, sorted
+pub fn fixtures(dir: &str) -> Vec<String> {
+  let mut names: Vec<String> = fs::read_dir(fixture(dir, ""))
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+    .collect();
+  names.sort();
+  names
+}
+
+/// A cache with the `B.S.A` geometry a fixture's name ends with
+pub fn fixture_cache(name: &str) -> Cache {
+  let sizes: Vec<usize> = name
+    .rsplitn(4, '.')
+    .take(3)
+    .map(|size| size.parse().unwrap())
+    .collect();
+  Cache::new(sizes[2], sizes[1], sizes[0])
+}
+
+/// Assembles one of the programs under `tests/assembly/`
+pub fn assembly(name: &str) -> Program {
+  assemble(&fs::read_to_string(fixture("assembly", name)).unwrap()).unwrap()
+}
+
+/// Reads one of the programs under `tests/machine_code/`
+pub fn machine_code(name: &str) -> Program {
+  parse_machine_code(&fs::read_to_string(fixture("machine_code", name)).unwrap()).unwrap()
+}
+
+/// Runs a loaded machine until it halts, returning the trace record of every instruction
+pub fn run_traced(machine: &mut Machine) -> Vec<TraceRecord> {
+  let mut records = Vec::new();
+  while machine.step().unwrap() {
+    records.push(*machine.last_step().unwrap());
+  }
+  records
+}
+
+/// A cycle-level model of the datapath, to compare with `Machine`
+pub trait Model {
+  fn load(&mut self, program: &Program);
+  /// Runs the loaded program until the model halts
+  fn run(&mut self);
+  fn registers(&self) -> &[i32];
+  fn memory_system(&self) -> &MemorySystem;
+  fn instructions(&self) -> usize;
+}
+
+impl Model for PipelineMachine {
+  fn load(&mut self, program: &Program) {
+    PipelineMachine::load(self, program).unwrap();
+  }
+
+  fn run(&mut self) {
+    PipelineMachine::run(self).unwrap();
+  }
+
+  fn registers(&self) -> &[i32] {
+    PipelineMachine::registers(self)
+  }
+
+  fn memory_system(&self) -> &MemorySystem {
+    PipelineMachine::memory_system(self)
+  }
+
+  fn instructions(&self) -> usize {
+    self.stats().instructions
+  }
+}
+
+impl Model for MulticycleMachine {
+  fn load(&mut self, program: &Program) {
+    MulticycleMachine::load(self, program).unwrap();
+  }
+
+  fn run(&mut self) {
+    MulticycleMachine::run(self).unwrap();
+  }
+
+  fn registers(&self) -> &[i32] {
+    MulticycleMachine::registers(self)
+  }
+
+  fn memory_system(&self) -> &MemorySystem {
+    MulticycleMachine::memory_system(self)
+  }
+
+  fn instructions(&self) -> usize {
+    self.stats().instructions
+  }
+}
+
+/// Runs every program under `tests/machine_code/` on the single-cycle machine and on a model
+/// made by `new`, both with a 2.2.2 cache, and checks they end with the same registers and
+/// memory after the same number of instructions. `check` is then given both for anything else to compare.
+pub fn compare_with_single_cycle<M: Model>(
+  new: impl Fn(Cache) -> M,
+  check: impl Fn(&str, &M, &Machine),
+) {
+  let names = fixtures("machine_code");
+  assert!(!names.is_empty());
+  for name in &names {
+    let program = machine_code(name);
+    let mut machine = Machine::new(Cache::new(2, 2, 2));
+    machine.load(&program).unwrap();
+    machine.run().unwrap();
+    let mut model = new(Cache::new(2, 2, 2));
+    model.load(&program);
+    model.run();
+
+    assert_eq!(model.registers(), machine.registers(), "{}", name);
+    assert_eq!(
+      model.memory_system().memory(),
+      machine.memory_system().memory(),
+      "{}",
+      name
+    );
+    assert_eq!(
+      model.instructions(),
+      machine.instructions_executed(),
+      "{}",
+      name
+    );
+    check(name, &model, &machine);
+  }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use cda3100_lc_sim::assembler::Program;
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::debugger::{Debugger, Point, Stop};
+use cda3100_lc_sim::Machine;
+
+fn load(name: &str) -> (Debugger, Program) {
+  let program = common::assembly(name);
+  let mut machine = Machine::new(Cache::new(2, 2, 2));
+  machine.load(&program).unwrap();
+  (Debugger::new(machine), program)
+}
+
+#[test]
+fn breakpoint_stops_every_time_through_the_loop() {
+  let (mut debugger, program) = load("jas21ba_test1.as.2.2.2");
+  let start = program.label_address("start").unwrap() as u32;
+  let id = debugger.add_point(Point::Breakpoint(start));
+
+  for expected in [10, 15, 20, 25] {
+    assert_eq!(
+      debugger.continue_running().unwrap(),
+      Stop::Breakpoint { id, pc: start }
+    );
+    assert_eq!(debugger.machine().register(1), expected);
+  }
+  assert!(debugger.delete_point(id));
+  assert_eq!(debugger.continue_running().unwrap(), Stop::Halted);
+}
+
+#[test]
+fn watchpoints_report_changes() {
+  let (mut debugger, program) = load("jas21ba_test1.as.2.2.2");
+  let ten = program.label_address("ten").unwrap();
+  let memory = debugger.add_point(Point::Memory(ten));
+  let register = debugger.add_point(Point::Register(3));
+
+  assert_eq!(
+    debugger.continue_running().unwrap(),
+    Stop::Watchpoint {
+      id: register,
+      old: 0,
+      new: 5
+    }
+  );
+  assert_eq!(debugger.machine().pc(), 3);
+  assert_eq!(
+    debugger.continue_running().unwrap(),
+    Stop::Watchpoint {
+      id: memory,
+      old: 10,
+      new: 30
+    }
+  );
+  assert_eq!(debugger.continue_running().unwrap(), Stop::Halted);
+}
+
+#[test]
+fn step_counts_instructions() {
+  let (mut debugger, _) = load("jas21ba_test1.as.2.2.2");
+  assert_eq!(debugger.step(3).unwrap(), Stop::Stepped);
+  assert_eq!(debugger.machine().instructions_executed(), 3);
+  assert_eq!(debugger.step(100).unwrap(), Stop::Halted);
+  assert_eq!(debugger.machine().instructions_executed(), 16);
+}
+
+#[test]
+fn commands_modify_registers_and_memory() {
+  let (mut debugger, program) = load("jas21ba_test1.as.2.2.2");
+  let ten = program.label_address("ten").unwrap();
+
+  for command in ["break start", "continue", "set reg 1 25", "set mem ten 99"] {
+    assert!(debugger.execute(command));
+  }
+  // The block holding `ten` is cached, the change has to show up there too
+  assert_eq!(debugger.machine().memory_system().peek_memory(ten), 99);
+  assert_eq!(debugger.machine().memory_system().memory()[ten], 99);
+
+  // reg1 goes from 25 to 30 on the next add, so the loop ends and 30 is stored over 99
+  assert!(debugger.execute("delete 1"));
+  assert!(debugger.execute("continue"));
+  assert!(debugger.machine().is_halted());
+  assert_eq!(debugger.machine().memory_system().memory()[ten], 30);
+  assert_eq!(debugger.machine().instructions_executed(), 7);
+}
+
+#[test]
+fn bad_commands_keep_the_debugger_running() {
+  let (mut debugger, _) = load("jas21ba_test1.as.2.2.2");
+  for command in [
+    "bogus",
+    "break nowhere",
+    "set reg 9 1",
+    "print mem",
+    "watch mem 70000",
+  ] {
+    assert!(debugger.execute(command));
+  }
+  assert_eq!(debugger.machine().instructions_executed(), 0);
+  assert!(!debugger.execute("quit"));
+}
+
+#[test]
+fn back_undoes_instructions() {
+  let (mut debugger, _) = load("jas21ba_test1.as.2.2.2");
+  assert!(debugger.execute("continue"));
+  assert!(debugger.machine().is_halted());
+
+  assert!(debugger.execute("back 4"));
+  assert!(!debugger.machine().is_halted());
+  assert_eq!(debugger.machine().instructions_executed(), 12);
+  assert_eq!(debugger.machine().register(1), 25);
+
+  // Running forward again ends up in the same place
+  assert!(debugger.execute("continue"));
+  assert_eq!(debugger.machine().instructions_executed(), 16);
+  assert_eq!(debugger.machine().register(1), 30);
+  assert_eq!(debugger.step_back(100), 16);
+}
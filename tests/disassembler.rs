@@ -0,0 +1,41 @@
+mod common;
+
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::disassembler::disassemble;
+
+/// Assembling the disassembly of every program under `tests/machine_code/` must give back the
+/// same machine code
+#[test]
+fn round_trips_through_the_assembler() {
+  let names = common::fixtures("machine_code");
+  assert!(!names.is_empty());
+  for name in names {
+    let words = common::machine_code(&name).words;
+    let program = assemble(&disassemble(&words)).unwrap();
+    assert_eq!(program.words, words, "{}", name);
+  }
+}
+
+#[test]
+fn labels_branch_targets() {
+  let assembly = disassemble(&common::machine_code("jas21ba_test1.mc.2.2.2").words);
+  let lines: Vec<&str> = assembly.lines().collect();
+  assert_eq!(lines[3], "L3     add 1 3 1");
+  assert_eq!(lines[4], "       beq 2 1 L6");
+  assert_eq!(lines[5], "       beq 0 0 L3");
+  assert_eq!(lines[6], "L6     sw 0 1 8");
+  assert_eq!(lines[8], "       .fill 10");
+}
+
+#[test]
+fn data_after_a_subroutine_is_not_code() {
+  let assembly = disassemble(&common::machine_code("jas21ba_test6.mc.2.2.2").words);
+  let lines: Vec<&str> = assembly.lines().collect();
+  // The subroutine at 6 is only reached through the pointer loaded into register 4
+  assert_eq!(lines[6], "       add 1 1 1");
+  assert_eq!(lines[7], "       jalr 7 6");
+  assert_eq!(
+    &lines[8..],
+    &["       .fill 5", "       .fill 6", "       .fill 0"]
+  );
+}
@@ -0,0 +1,91 @@
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::parser::{encode, parse_instruction};
+use cda3100_lc_sim::types::*;
+
+/// The bits each opcode actually uses, anything else is ignored when decoding
+fn used_bits(word: i32) -> i32 {
+  match (word >> 22) & 0b111 {
+    // add and nand
+    0 | 1 => 0x1FF_0007,
+    // jalr
+    5 => 0x1FF_0000,
+    // halt and noop
+    6 | 7 => 0x1C0_0000,
+    // lw, sw and beq
+    _ => 0x1FF_FFFF,
+  }
+}
+
+/// Decoding then encoding every 25-bit pattern gives back the word with the unused bits
+/// cleared, and decoding that again gives back the same instruction
+#[test]
+fn encode_inverts_parse_instruction() {
+  for word in 0..1 << 25 {
+    let instruction = parse_instruction(word);
+    let encoded = encode(&instruction);
+    assert_eq!(encoded, word & used_bits(word), "{:#x}", word);
+    assert_eq!(parse_instruction(encoded), instruction, "{:#x}", word);
+  }
+}
+
+/// The text of an instruction assembles back to the same word, checked for every register
+/// combination of every opcode and a spread of offsets
+#[test]
+fn display_assembles_back_to_the_same_word() {
+  let offsets = [-32768, -1000, -1, 0, 1, 7, 1000, 32767];
+  let mut source = String::new();
+  let mut expected = Vec::new();
+  for opcode in 0..8 {
+    for registers in 0..64 {
+      for offset in offsets {
+        let word = (opcode << 22) | (registers << 16) | (offset & 0xFFFF);
+        let word = word & used_bits(word);
+        source.push_str(&format!("\t{}\n", parse_instruction(word)));
+        expected.push(word);
+      }
+    }
+  }
+  assert_eq!(assemble(&source).unwrap().words, expected);
+}
+
+#[test]
+fn displays_like_assembly() {
+  let instructions = [
+    (
+      OpType::R(RType {
+        code: RTypeOpcode::Nand,
+        register_a: 1,
+        register_b: 2,
+        destination: 3,
+      }),
+      "nand 1 2 3",
+    ),
+    (
+      OpType::I(IType {
+        code: ITypeOpcode::BranchEq,
+        register_a: 0,
+        register_b: 1,
+        offset: -3,
+      }),
+      "beq 0 1 -3",
+    ),
+    (
+      OpType::J(JType {
+        code: JTypeOpcode::JumpAndLink,
+        register_a: 4,
+        register_b: 7,
+      }),
+      "jalr 4 7",
+    ),
+    (
+      OpType::O(OType {
+        code: OTypeOpcode::Halt,
+      }),
+      "halt",
+    ),
+  ];
+  for (instruction, text) in instructions {
+    assert_eq!(instruction.to_string(), text);
+    assert_eq!(parse_instruction(encode(&instruction)), instruction);
+  }
+}
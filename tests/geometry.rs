@@ -0,0 +1,55 @@
+use cda3100_lc_sim::cache::{Cache, CacheConfig};
+use cda3100_lc_sim::SimError;
+
+fn validation_error(block_size: usize, sets: usize, blocks_per_set: usize) -> String {
+  match CacheConfig::new(block_size, sets, blocks_per_set).validate() {
+    Err(SimError::InvalidCache(message)) => message,
+    other => panic!(
+      "{}.{}.{} gave {:?}",
+      block_size, sets, blocks_per_set, other
+    ),
+  }
+}
+
+#[test]
+fn rejects_unusable_geometry() {
+  assert_eq!(
+    validation_error(0, 2, 2),
+    "The block size of cache 0.2.2 can't be 0"
+  );
+  assert_eq!(
+    validation_error(2, 0, 2),
+    "The number of sets of cache 2.0.2 can't be 0"
+  );
+  assert_eq!(
+    validation_error(2, 2, 6),
+    "The number of blocks per set of cache 2.2.6 must be a power of 2, not 6"
+  );
+  assert_eq!(
+    validation_error(1 << 17, 1, 1),
+    "The 131072 word blocks of cache 131072.1.1 run past the end of the 65536 words of memory"
+  );
+  assert_eq!(
+    validation_error(1024, 128, 1),
+    "Cache 1024.128.1 holds more words than the 65536 words of memory"
+  );
+  // Large enough to overflow when multiplied out
+  assert!(CacheConfig::new(1 << 16, 1 << 32, 1 << 32)
+    .validate()
+    .is_err());
+}
+
+#[test]
+fn accepts_a_cache_as_large_as_memory() {
+  assert!(CacheConfig::new(1 << 16, 1, 1).validate().is_ok());
+  assert!(CacheConfig::new(16, 1024, 4).validate().is_ok());
+  assert!(CacheConfig::new(1, 1, 1).validate().is_ok());
+}
+
+#[test]
+fn splits_addresses_into_tag_set_and_offset() {
+  assert_eq!(Cache::new(1, 1, 1).address_split(), (16, 0, 0));
+  assert_eq!(Cache::new(2, 2, 2).address_split(), (14, 1, 1));
+  assert_eq!(Cache::new(8, 4, 2).address_split(), (11, 2, 3));
+  assert_eq!(Cache::new(1 << 16, 1, 1).address_split(), (0, 0, 16));
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::cache::{AccessKind, Cache, CacheConfig, WritePolicy};
+use cda3100_lc_sim::memory::Hierarchy;
+use cda3100_lc_sim::replacement::ReplacementKind;
+use cda3100_lc_sim::Machine;
+
+#[test]
+fn program_runs_through_three_levels() {
+  let program = common::assembly("jas21ba_test4.as.2.2.2");
+  let caches = vec![
+    Cache::new(1, 2, 1),
+    Cache::new(4, 2, 2),
+    Cache::new(8, 2, 2),
+  ];
+  let mut machine = Machine::with_hierarchy(caches);
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+
+  let names: Vec<&str> = machine
+    .memory_system()
+    .caches()
+    .iter()
+    .map(Cache::name)
+    .collect();
+  assert_eq!(names, ["L1 cache", "L2 cache", "L3 cache"]);
+  assert_eq!(
+    &machine.memory_system().memory()[10..16],
+    &[1, 2, 3, 4, 5, 3]
+  );
+
+  let l1 = machine.memory_system().caches()[0].stats();
+  let l2 = machine.memory_system().caches()[1].stats();
+  let l3 = machine.memory_system().caches()[2].stats();
+  // Every L1 miss fills a block from the L2 and every dirty L1 eviction writes one back to it
+  assert_eq!(l1.misses(), 18);
+  assert_eq!(l2.reads(), l1.misses());
+  assert_eq!(l2.writes(), l1.dirty_evictions);
+  // The L2 only misses on its 4 blocks
+  assert_eq!(l2.misses(), 4);
+  assert_eq!(l3.misses(), 2);
+  assert_eq!(l3.words_from_memory, 16);
+}
+
+#[test]
+fn split_l1_keeps_instructions_and_data_apart() {
+  let program = common::assembly("jas21ba_test4.as.2.2.2");
+  let mut machine = Machine::with_split_l1(Cache::new(2, 2, 2), Cache::new(2, 2, 2), vec![]);
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+  assert_eq!(
+    &machine.memory_system().memory()[10..16],
+    &[1, 2, 3, 4, 5, 3]
+  );
+
+  let instruction_cache = machine.memory_system().instruction_cache().unwrap();
+  let data_cache = machine.memory_system().cache();
+  assert_eq!(instruction_cache.name(), "instruction cache");
+  assert_eq!(data_cache.name(), "data cache");
+
+  let instruction_stats = instruction_cache.stats();
+  let data_stats = data_cache.stats();
+  assert_eq!(instruction_stats.reads(), 10);
+  assert_eq!(
+    instruction_stats.load_hits + instruction_stats.load_misses,
+    0
+  );
+  assert_eq!(
+    data_stats.instruction_hits + data_stats.instruction_misses,
+    0
+  );
+  // A single 2.2.2 cache has 5 evictions on this program as instructions and data evict
+  // each other, split caches only have the one
+  assert_eq!(instruction_stats.clean_evictions, 1);
+  assert_eq!(data_stats.clean_evictions + data_stats.dirty_evictions, 0);
+}
+
+#[test]
+fn split_l1_shares_the_l2() {
+  let program = common::assembly("jas21ba_test4.as.2.2.2");
+  let mut machine = Machine::with_split_l1(
+    Cache::new(2, 2, 2),
+    Cache::new(2, 2, 2),
+    vec![Cache::new(4, 4, 2)],
+  );
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+  assert_eq!(
+    &machine.memory_system().memory()[10..16],
+    &[1, 2, 3, 4, 5, 3]
+  );
+
+  assert_eq!(machine.memory_system().caches()[1].name(), "L2 cache");
+  let instruction_stats = machine.memory_system().instruction_cache().unwrap().stats();
+  let l2 = machine.memory_system().caches()[1].stats();
+  assert_eq!(
+    l2.instruction_hits + l2.instruction_misses,
+    instruction_stats.misses()
+  );
+  assert_eq!(
+    l2.load_hits + l2.load_misses,
+    machine.memory_system().cache().stats().misses()
+  );
+}
+
+#[test]
+fn split_instruction_cache_keeps_running_stale_instructions() {
+  let source = "        lw   0 1 stop
+        sw   0 1 patch
+patch   lw   0 2 two       the store replaces this with a halt
+        halt
+stop    .fill 25165824
+two     .fill 2
+";
+  let program = assemble(source).unwrap();
+  let patch = program.label_address("patch").unwrap();
+
+  // A shared L1 holds the stored halt, so it is what gets fetched
+  let mut machine = Machine::new(Cache::new(4, 1, 1));
+  machine.load(&program).unwrap();
+  assert_eq!(machine.run().unwrap(), 3);
+  assert_eq!(machine.register(2), 0);
+
+  // The instruction cache already has the block and nothing updates it, so the old lw runs.
+  // The halt is still only in the dirty data cache block until the machine halts.
+  let mut machine = Machine::with_split_l1(Cache::new(4, 1, 1), Cache::new(4, 1, 1), vec![]);
+  machine.load(&program).unwrap();
+  assert_eq!(machine.run().unwrap(), 4);
+  assert_eq!(machine.register(2), 2);
+  assert_eq!(machine.memory_system().memory()[patch], 25165824);
+}
+
+#[test]
+fn dirty_eviction_goes_to_the_next_level() {
+  let mut l1 = Cache::new(1, 1, 1);
+  let mut lower = vec![Cache::new(2, 2, 1)];
+  let mut memory = vec![0; 16];
+
+  let mut below = Hierarchy {
+    caches: &mut lower,
+    memory: &mut memory,
+  };
+  l1.set_value(0, &mut below, 5);
+  // Evicts the dirty block holding address 0 from the L1
+  l1.get_value(2, &mut below, AccessKind::Load);
+  assert_eq!(l1.peek(0), None);
+  assert_eq!(lower[0].peek(0), Some(5));
+  assert_eq!(lower[0].stats().store_hits, 1);
+  assert_eq!(memory[0], 0);
+
+  Hierarchy {
+    caches: &mut lower,
+    memory: &mut memory,
+  }
+  .flush();
+  assert_eq!(memory[0], 5);
+}
+
+#[test]
+fn larger_blocks_above_read_several_blocks_below() {
+  let mut l1 = Cache::new(4, 1, 1);
+  let mut lower = vec![Cache::new(1, 4, 1)];
+  let mut memory: Vec<i32> = (0..16).collect();
+
+  let mut below = Hierarchy {
+    caches: &mut lower,
+    memory: &mut memory,
+  };
+  assert_eq!(l1.get_value(6, &mut below, AccessKind::Load), 6);
+  let l2 = lower[0].stats();
+  assert_eq!((l2.load_hits, l2.load_misses), (0, 4));
+  assert_eq!(l2.words_from_memory, 4);
+  assert_eq!(lower[0].peek(4), Some(4));
+}
+
+#[test]
+fn write_through_level_keeps_memory_current() {
+  let mut l1 = Cache::new(1, 1, 1);
+  let mut lower = vec![Cache::from_config(&CacheConfig {
+    write_policy: WritePolicy::Through,
+    ..CacheConfig::new(2, 1, 1)
+  })];
+  let mut memory = vec![0; 16];
+
+  let mut below = Hierarchy {
+    caches: &mut lower,
+    memory: &mut memory,
+  };
+  l1.set_value(3, &mut below, 7);
+  l1.flush(&mut below);
+  assert_eq!(memory[3], 7);
+  assert_eq!(lower[0].stats().write_throughs, 1);
+}
+
+#[test]
+fn parses_cache_level_specs() {
+  let config: CacheConfig = "4.8.2,replacement=fifo,write=through,write-buffer=2"
+    .parse()
+    .unwrap();
+  assert_eq!(
+    (
+      config.block_size_in_words,
+      config.number_of_sets,
+      config.blocks_per_set
+    ),
+    (4, 8, 2)
+  );
+  assert_eq!(config.replacement, ReplacementKind::Fifo);
+  assert_eq!(config.write_policy, WritePolicy::Through);
+  assert_eq!(config.write_buffer_size, 2);
+  assert!(config.validate().is_ok());
+
+  assert!("4.8".parse::<CacheConfig>().is_err());
+  assert!("4.8.2,colour=red".parse::<CacheConfig>().is_err());
+  assert!("3.8.2".parse::<CacheConfig>().unwrap().validate().is_err());
+}
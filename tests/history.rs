@@ -0,0 +1,128 @@
+mod common;
+
+use cda3100_lc_sim::assembler::Program;
+use cda3100_lc_sim::cache::{Cache, CacheConfig, WritePolicy};
+use cda3100_lc_sim::replacement::ReplacementKind;
+use cda3100_lc_sim::Machine;
+
+/// Everything visible about the machine, including the contents of every cache block
+fn fingerprint(machine: &Machine) -> String {
+  let mut fingerprint = format!(
+    "{} {:?} {} {} {:?}",
+    machine.pc(),
+    machine.registers(),
+    machine.is_halted(),
+    machine.instructions_executed(),
+    &machine.memory_system().memory()[..64]
+  );
+  for cache in machine.memory_system().caches() {
+    fingerprint.push_str(&format!(" {:?}", cache.stats()));
+    for set_index in 0..cache.set_count() {
+      for block in cache.blocks(set_index) {
+        fingerprint.push_str(&format!(
+          " {}{}{}:{:?}",
+          block.is_valid(),
+          block.is_dirty(),
+          block.tag(),
+          block.data()
+        ));
+      }
+    }
+  }
+  fingerprint
+}
+
+fn machine_with(l1: CacheConfig, program: &Program) -> Machine {
+  let l2 = CacheConfig {
+    replacement: l1.replacement,
+    ..CacheConfig::new(2, 2, 2)
+  };
+  let mut machine = Machine::with_hierarchy(vec![Cache::from_config(&l1), Cache::from_config(&l2)]);
+  machine.load(program).unwrap();
+  machine.record_history(true);
+  machine
+}
+
+#[test]
+fn stepping_back_restores_every_earlier_state() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut configs: Vec<CacheConfig> = [
+    ReplacementKind::Lru,
+    ReplacementKind::Fifo,
+    ReplacementKind::Random,
+    ReplacementKind::Plru,
+    ReplacementKind::Lfu,
+  ]
+  .into_iter()
+  .map(|replacement| CacheConfig {
+    replacement,
+    ..CacheConfig::new(1, 2, 2)
+  })
+  .collect();
+  configs.push(CacheConfig {
+    write_policy: WritePolicy::Through,
+    write_buffer_size: 2,
+    ..CacheConfig::new(1, 2, 2)
+  });
+
+  for config in configs {
+    let replacement = config.replacement;
+    let mut machine = machine_with(config, &program);
+    let mut states = vec![fingerprint(&machine)];
+    while machine.step().unwrap() {
+      states.push(fingerprint(&machine));
+    }
+    assert_eq!(machine.history_len(), 94);
+
+    for expected in states.iter().rev().skip(1) {
+      assert_eq!(machine.step_back(1), 1);
+      assert_eq!(&fingerprint(&machine), expected, "{:?}", replacement);
+    }
+    assert_eq!(machine.step_back(1), 0);
+
+    // Running forward again makes the same choices, so the replacement bookkeeping was
+    // restored along with the blocks
+    for expected in states.iter().skip(1) {
+      machine.step().unwrap();
+      assert_eq!(&fingerprint(&machine), expected, "{:?}", replacement);
+    }
+  }
+}
+
+#[test]
+fn step_back_several_at_once() {
+  let program = common::assembly("jas21ba_test1.as.2.2.2");
+  let ten = program.label_address("ten").unwrap();
+  let mut machine = Machine::new(Cache::new(2, 2, 2));
+  machine.load(&program).unwrap();
+  machine.record_history(true);
+  machine.run().unwrap();
+  assert_eq!(machine.memory_system().memory()[ten], 30);
+
+  // Undo the halt (which wrote the dirty block back) and the store
+  assert_eq!(machine.step_back(2), 2);
+  assert!(!machine.is_halted());
+  assert_eq!(machine.memory_system().memory()[ten], 10);
+  assert_eq!(machine.memory_system().peek_memory(ten), 10);
+  assert_eq!(machine.pc(), 6);
+
+  assert_eq!(machine.step_back(100), 14);
+  assert_eq!(machine.pc(), 0);
+  assert_eq!(machine.registers(), &[0; 8]);
+  assert_eq!(machine.memory_system().cache().stats().reads(), 0);
+}
+
+#[test]
+fn no_history_unless_recording() {
+  let program = common::assembly("jas21ba_test1.as.2.2.2");
+  let mut machine = Machine::new(Cache::from_config(&CacheConfig {
+    write_policy: WritePolicy::Through,
+    write_buffer_size: 2,
+    ..CacheConfig::new(2, 2, 2)
+  }));
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+  assert_eq!(machine.history_len(), 0);
+  assert_eq!(machine.step_back(1), 0);
+  assert!(machine.is_halted());
+}
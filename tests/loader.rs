@@ -0,0 +1,33 @@
+use cda3100_lc_sim::loader::{is_assembly_source, load_program};
+use cda3100_lc_sim::SimError;
+use std::path::Path;
+
+#[test]
+fn detects_assembly_from_extension_or_opcode() {
+  let machine_code = Path::new("program.mc");
+  assert!(is_assembly_source(Path::new("test.as.2.2.2"), "8454155\n"));
+  assert!(is_assembly_source(machine_code, "        lw   0 1 five\n"));
+  assert!(is_assembly_source(machine_code, "start   halt\n"));
+  assert!(!is_assembly_source(machine_code, "8454155\n25165824\n"));
+  assert!(!is_assembly_source(machine_code, "abc\n5\n"));
+  assert!(!is_assembly_source(machine_code, ""));
+}
+
+#[test]
+fn bad_first_line_of_machine_code_reports_line_number() {
+  let path = std::env::temp_dir().join("cda3100_lc_sim_bad_first_line.mc");
+  std::fs::write(&path, "abc\n5\n").unwrap();
+
+  match load_program(&path, false) {
+    Err(SimError::BadInput { line, text }) => {
+      assert_eq!(line, 1);
+      assert_eq!(text, "abc");
+    }
+    _ => panic!("expected bad input"),
+  }
+  // Forcing it through the assembler still reports the assembler's error
+  assert!(matches!(
+    load_program(&path, true),
+    Err(SimError::Assemble(_))
+  ));
+}
@@ -0,0 +1,210 @@
+mod common;
+
+use cda3100_lc_sim::assembler::{assemble, Program};
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::loader::parse_machine_code;
+use cda3100_lc_sim::{Machine, OverflowPolicy, SimError};
+
+/// Loads one of the programs under `tests/assembly/` into a machine with the cache its name ends
+/// with
+fn load(name: &str) -> (Machine, Program) {
+  let program = common::assembly(name);
+
+  let mut machine = Machine::new(common::fixture_cache(name));
+  machine.load(&program).unwrap();
+  (machine, program)
+}
+
+#[test]
+fn loop_stores_final_answer() {
+  let (mut machine, program) = load("jas21ba_test1.as.2.2.2");
+  machine.run().unwrap();
+
+  let ten = program.label_address("ten").unwrap();
+  assert_eq!(machine.memory_system().memory()[ten], 30);
+  assert_eq!(machine.register(1), 30);
+}
+
+#[test]
+fn add_and_nand() {
+  let (mut machine, _) = load("jas21ba_test2.as.2.1.4");
+  machine.run().unwrap();
+
+  assert_eq!(machine.register(3), -6);
+  assert_eq!(machine.register(5), 4);
+}
+
+#[test]
+fn fibonacci() {
+  let (mut machine, program) = load("jas21ba_test3.as.2.2.2");
+  assert_eq!(machine.run().unwrap(), 94);
+
+  let final_address = program.label_address("final").unwrap();
+  assert_eq!(machine.memory_system().memory()[final_address], 610);
+}
+
+#[test]
+fn jalr_calls_and_returns_from_subroutine() {
+  let (mut machine, program) = load("jas21ba_test6.as.2.2.2");
+  let double = program.label_address("double").unwrap() as u32;
+
+  // First call jumps into the subroutine with the return address in reg7
+  assert!(machine.run_until(|machine| machine.pc() == double).unwrap());
+  assert_eq!(machine.register(7), 3);
+
+  machine.run().unwrap();
+  let result = program.label_address("result").unwrap();
+  assert_eq!(machine.memory_system().memory()[result], 20);
+  assert_eq!(machine.register(1), 20);
+  // The return from the second call leaves the return address of the second jalr behind
+  assert_eq!(machine.register(7), 4);
+  assert!(machine.is_halted());
+}
+
+#[test]
+fn jalr_with_same_register_jumps_to_next_instruction() {
+  let program = assemble("        jalr 1 1\n        halt\n").unwrap();
+  let mut machine = Machine::new(Cache::new(1, 1, 1));
+  machine.load(&program).unwrap();
+
+  assert_eq!(machine.run().unwrap(), 2);
+  assert_eq!(machine.register(1), 1);
+}
+
+#[test]
+fn dirty_eviction_writes_back_to_victim_address() {
+  let (mut machine, program) = load("jas21ba_test7.as.1.1.1");
+  assert_eq!(machine.run().unwrap(), 5);
+
+  let first = program.label_address("first").unwrap();
+  assert_eq!(machine.memory_system().memory()[first], 5);
+  assert_eq!(machine.register(2), 7);
+  assert_eq!(machine.register(3), 5);
+  // The program itself must not have been overwritten by the evicted block
+  assert_eq!(
+    machine.memory_system().memory()[..first],
+    program.words[..first]
+  );
+}
+
+#[test]
+fn conflicting_stores_and_loads_keep_memory_intact() {
+  // a and b map to the same set, each store must survive the other evicting it
+  let source = "        lw   0 1 one
+        sw   0 1 a
+        lw   0 2 a
+        add  1 2 2
+        sw   0 2 b
+        lw   0 3 a
+        lw   0 4 b
+        halt
+one     .fill 1
+a       .fill 0
+b       .fill 0
+";
+  let program = assemble(source).unwrap();
+  let mut machine = Machine::new(Cache::new(1, 2, 1));
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+
+  let a = program.label_address("a").unwrap();
+  let b = program.label_address("b").unwrap();
+  assert_eq!(machine.memory_system().memory()[a], 1);
+  assert_eq!(machine.memory_system().memory()[b], 2);
+  assert_eq!(machine.register(3), 1);
+  assert_eq!(machine.register(4), 2);
+  assert_eq!(machine.memory_system().memory()[..8], program.words[..8]);
+}
+
+fn machine_for(source: &str) -> Machine {
+  let program = assemble(source).unwrap();
+  let mut machine = Machine::new(Cache::new(2, 2, 2));
+  machine.load(&program).unwrap();
+  machine
+}
+
+#[test]
+fn negative_data_address_is_an_error() {
+  let mut machine = machine_for("        noop\n        lw   0 1 -1\n        halt\n");
+
+  match machine.run() {
+    Err(SimError::AddressOutOfRange { address, pc }) => {
+      assert_eq!(address, -1);
+      assert_eq!(pc, 1);
+    }
+    _ => panic!("expected an out of range address"),
+  }
+}
+
+#[test]
+fn branch_before_address_zero_is_an_error() {
+  let mut machine = machine_for("        beq  0 0 -5\n        halt\n");
+
+  assert!(matches!(
+    machine.run(),
+    Err(SimError::PcOutOfRange { pc: -4 })
+  ));
+}
+
+#[test]
+fn jalr_to_bad_target_leaves_link_register_untouched() {
+  let mut machine =
+    machine_for("        lw   0 1 neg\n        jalr 1 2\n        halt\nneg     .fill -1\n");
+
+  assert!(matches!(
+    machine.run(),
+    Err(SimError::PcOutOfRange { pc: -1 })
+  ));
+  assert_eq!(machine.register(2), 0);
+}
+
+#[test]
+fn add_overflow_follows_policy() {
+  let source = "        lw   0 1 max\n        add  1 1 2\n        halt\nmax     .fill 2147483647\n";
+
+  let mut machine = machine_for(source);
+  machine.run().unwrap();
+  assert_eq!(machine.register(2), -2);
+
+  let mut machine = machine_for(source);
+  machine.set_overflow_policy(OverflowPolicy::Trap);
+  assert!(matches!(
+    machine.run(),
+    Err(SimError::ArithmeticOverflow { pc: 1, .. })
+  ));
+}
+
+#[test]
+fn bad_machine_code_reports_line_number() {
+  match parse_machine_code("8454155\n12x\n") {
+    Err(SimError::BadInput { line, text }) => {
+      assert_eq!(line, 2);
+      assert_eq!(text, "12x");
+    }
+    _ => panic!("expected bad input"),
+  }
+}
+
+#[test]
+fn cache_statistics() {
+  let (mut machine, _) = load("jas21ba_test4.as.2.2.2");
+  machine.run().unwrap();
+
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!(stats.reads(), 16);
+  assert_eq!(stats.writes(), 2);
+  assert_eq!((stats.instruction_hits, stats.instruction_misses), (5, 5));
+  assert_eq!((stats.load_hits, stats.load_misses), (3, 3));
+  assert_eq!((stats.store_hits, stats.store_misses), (1, 1));
+  assert_eq!((stats.clean_evictions, stats.dirty_evictions), (5, 0));
+  assert_eq!(stats.halt_writebacks, 2);
+  assert_eq!(stats.words_from_memory, 18);
+  assert_eq!(stats.words_to_memory, 4);
+
+  // More blocks per set keeps the data blocks from evicting each other
+  let (mut machine, _) = load("jas21ba_test5.as.2.2.4");
+  machine.run().unwrap();
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!(stats.clean_evictions + stats.dirty_evictions, 0);
+  assert_eq!(stats.misses(), 8);
+}
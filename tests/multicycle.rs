@@ -0,0 +1,91 @@
+mod common;
+
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::multicycle::{ControlState, MulticycleMachine};
+use cda3100_lc_sim::SimError;
+
+#[test]
+fn multicycle_ends_in_the_same_state_as_the_single_cycle_machine() {
+  common::compare_with_single_cycle(MulticycleMachine::new, |name, multicycle, machine| {
+    // The caches see the same accesses in the same order
+    assert_eq!(
+      multicycle.memory_system().cache().stats().to_json(),
+      machine.memory_system().cache().stats().to_json(),
+      "{}",
+      name
+    );
+  });
+}
+
+#[test]
+fn each_instruction_class_takes_its_own_number_of_cycles() {
+  let source = "\tlw 0 1 one
+\tadd 1 1 2
+\tsw 0 2 one
+\tbeq 0 0 next
+next\tnoop
+\tjalr 3 3
+\thalt
+one\t.fill 1
+";
+  let mut machine = MulticycleMachine::new(Cache::new(1, 1, 1));
+  machine.load(&assemble(source).unwrap()).unwrap();
+  machine.run().unwrap();
+
+  assert_eq!(machine.register(2), 2);
+  assert_eq!(machine.register(3), 6);
+  let stats = machine.stats();
+  assert_eq!(stats.instructions, 7);
+  assert_eq!(stats.cycles, 5 + 4 + 4 + 3 + 2 + 3 + 3);
+  let cycles = |state| stats.state_cycles.get(&state).copied().unwrap_or(0);
+  assert_eq!(cycles(ControlState::Fetch), 7);
+  assert_eq!(cycles(ControlState::Decode), 7);
+  assert_eq!(cycles(ControlState::Address), 2);
+  assert_eq!(cycles(ControlState::MemoryRead), 1);
+  assert_eq!(cycles(ControlState::MemoryWrite), 1);
+  assert_eq!(cycles(ControlState::Alu), 1);
+  assert_eq!(cycles(ControlState::Branch), 1);
+  assert_eq!(cycles(ControlState::Jump), 1);
+}
+
+#[test]
+fn control_steps_through_the_states_of_a_load() {
+  let mut machine = MulticycleMachine::new(Cache::new(1, 1, 1));
+  machine
+    .load(&assemble("\tlw 0 1 2\n\thalt\n\t.fill 9\n").unwrap())
+    .unwrap();
+  let mut states = Vec::new();
+  while !machine.is_halted() {
+    states.push(machine.control_state());
+    machine.step().unwrap();
+  }
+  assert_eq!(
+    states,
+    [
+      ControlState::Fetch,
+      ControlState::Decode,
+      ControlState::Address,
+      ControlState::MemoryRead,
+      ControlState::LoadWriteback,
+      ControlState::Fetch,
+      ControlState::Decode,
+      ControlState::Halt,
+    ]
+  );
+  assert_eq!(machine.register(1), 9);
+}
+
+#[test]
+fn jalr_to_bad_target_leaves_link_register_untouched() {
+  let mut machine = MulticycleMachine::new(Cache::new(1, 1, 1));
+  machine
+    .load(&assemble("\tlw 0 1 neg\n\tjalr 1 2\n\thalt\nneg\t.fill -1\n").unwrap())
+    .unwrap();
+
+  assert!(matches!(
+    machine.run(),
+    Err(SimError::PcOutOfRange { pc: -1 })
+  ));
+  assert_eq!(machine.register(2), 0);
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::memory::MemorySystem;
+use cda3100_lc_sim::pipeline::PipelineMachine;
+use cda3100_lc_sim::timing::MemoryTiming;
+use cda3100_lc_sim::SimError;
+
+fn run(source: &str) -> PipelineMachine {
+  let program = assemble(source).unwrap();
+  let mut machine = PipelineMachine::new(Cache::new(1, 1, 1));
+  machine.load(&program).unwrap();
+  machine.run().unwrap();
+  machine
+}
+
+#[test]
+fn pipeline_ends_in_the_same_state_as_the_single_cycle_machine() {
+  common::compare_with_single_cycle(PipelineMachine::new, |name, pipeline, _| {
+    // Four cycles to fill the pipeline, plus one for each bubble
+    let stats = pipeline.stats();
+    assert_eq!(
+      stats.cycles,
+      stats.instructions + 4 + stats.stalls + stats.squashed,
+      "{}",
+      name
+    );
+  });
+}
+
+#[test]
+fn forwarding_and_load_use_stalls() {
+  // Each add uses the result of the instruction right before it, only the one after the load
+  // has to wait
+  let machine = run(
+    "\tlw 0 1 five
+\tadd 1 1 2
+\tadd 2 1 3
+\tnand 3 2 4
+\thalt
+five\t.fill 5
+",
+  );
+  assert_eq!(&machine.registers()[1..5], &[5, 10, 15, !(15 & 10)]);
+  let stats = machine.stats();
+  assert_eq!(stats.stalls, 1);
+  assert_eq!(stats.cycles, 5 + 4 + 1);
+}
+
+#[test]
+fn taken_branches_squash_the_instructions_fetched_after_them() {
+  let machine = run(
+    "\tbeq 0 0 skip
+\tadd 0 0 0
+\tlw 0 1 one
+\tlw 0 1 one
+skip\tlw 0 2 one
+\tbeq 0 2 skip
+\thalt
+one\t.fill 1
+",
+  );
+  assert_eq!(machine.register(1), 0);
+  assert_eq!(machine.register(2), 1);
+  let stats = machine.stats();
+  // The first beq is taken and the three instructions after it are thrown away, the second
+  // isn't so nothing is lost
+  assert_eq!(stats.mispredictions, 1);
+  assert_eq!(stats.squashed, 3);
+  assert_eq!(stats.instructions, 4);
+}
+
+#[test]
+fn memory_transfers_are_timed() {
+  let mut memory = MemorySystem::new(vec![Cache::new(2, 2, 2)], None);
+  memory.set_memory_timing(MemoryTiming {
+    latency: 0,
+    word_cycles: 1,
+  });
+  memory
+    .load(&common::machine_code("jas21ba_test4.mc.2.2.2"))
+    .unwrap();
+  let mut machine = PipelineMachine::with_memory(memory);
+  machine.run().unwrap();
+
+  let memory = machine.memory_system();
+  let stats = memory.cache().stats();
+  let timing = memory.timing();
+  // With no latency every word moved to or from memory costs one cycle
+  assert_eq!(
+    timing.memory_cycles,
+    stats.words_from_memory + stats.words_to_memory
+  );
+  // The pipeline counts its own cycles per instruction
+  assert_eq!(timing.cpi(), None);
+}
+
+#[test]
+fn out_of_range_addresses_fail_only_if_the_access_completes() {
+  let program = assemble("\tlw 0 1 neg\n\tlw 1 2 0\n\thalt\nneg\t.fill -1\n").unwrap();
+  let mut machine = PipelineMachine::new(Cache::new(1, 1, 1));
+  machine.load(&program).unwrap();
+  assert!(matches!(
+    machine.run(),
+    Err(SimError::AddressOutOfRange { address: -1, pc: 1 })
+  ));
+
+  // The `lw` after the taken branch is squashed before it reaches MEM
+  let machine = run("\tlw 0 1 neg\n\tbeq 0 0 done\n\tlw 1 2 0\ndone\thalt\nneg\t.fill -1\n");
+  assert_eq!(machine.register(2), 0);
+}
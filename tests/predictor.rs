@@ -0,0 +1,80 @@
+use cda3100_lc_sim::assembler::assemble;
+use cda3100_lc_sim::cache::Cache;
+use cda3100_lc_sim::predictor::{last_branch, BranchStudy, PredictorConfig, PredictorKind};
+use cda3100_lc_sim::Machine;
+
+// The forward branch at pc 2 falls through three times before it exits the loop, the backward
+// branch at pc 4 is always taken
+const COUNTDOWN: &str = "\tlw 0 1 three
+\tlw 0 2 neg1
+loop\tbeq 1 0 done
+\tadd 1 2 1
+\tbeq 0 0 loop
+done\thalt
+three\t.fill 3
+neg1\t.fill -1
+";
+
+fn study(kinds: &[PredictorKind], config: PredictorConfig) -> BranchStudy {
+  let mut machine = Machine::new(Cache::new(1, 1, 1));
+  machine.load(&assemble(COUNTDOWN).unwrap()).unwrap();
+  let mut study = BranchStudy::new(kinds, &config);
+  while !machine.is_halted() {
+    machine.step().unwrap();
+    if let Some(branch) = last_branch(&machine) {
+      study.record(&branch);
+    }
+  }
+  study
+}
+
+#[test]
+fn branches_are_counted_per_pc() {
+  let study = study(&[PredictorKind::NotTaken], PredictorConfig::default());
+  let branches: Vec<(u32, (usize, usize))> = study
+    .branches()
+    .iter()
+    .map(|(pc, counts)| (*pc, *counts))
+    .collect();
+  assert_eq!(branches, [(2, (4, 1)), (4, (3, 3))]);
+}
+
+#[test]
+fn predictor_accuracy() {
+  let kinds = [
+    PredictorKind::NotTaken,
+    PredictorKind::Taken,
+    PredictorKind::Btfn,
+    PredictorKind::OneBit,
+    PredictorKind::TwoBit,
+  ];
+  let study = study(&kinds, PredictorConfig::default());
+  let correct: Vec<(usize, usize)> = study
+    .results()
+    .map(|(_, stats)| (stats.branches[&2].1, stats.branches[&4].1))
+    .collect();
+  // The 1-bit and 2-bit tables start out predicting not taken, so both miss the first time
+  // round the loop and on its exit
+  assert_eq!(correct, [(3, 0), (1, 3), (3, 3), (3, 2), (3, 2)]);
+  let (_, btfn) = study.results().nth(2).unwrap();
+  assert_eq!((btfn.correct, btfn.predictions), (6, 7));
+}
+
+#[test]
+fn btb_misses_fall_through() {
+  let config = PredictorConfig {
+    btb_size: Some(4),
+    ..PredictorConfig::default()
+  };
+  let study = study(&[PredictorKind::Taken], config);
+  let (_, stats) = study.results().next().unwrap();
+  // Only the backward branch is ever in the BTB by the time it is predicted again
+  assert_eq!((stats.btb_hits, stats.btb_misses), (2, 5));
+  assert_eq!((stats.branches[&2].1, stats.branches[&4].1), (3, 2));
+
+  let bad = PredictorConfig {
+    table_size: 12,
+    ..PredictorConfig::default()
+  };
+  assert!(bad.validate().is_err());
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+/// `--print-state` must keep the reference simulator's format: the state before every
+/// instruction, the `@@@` transfers in between, then the final state after the summary. The
+/// expected output comes from the original simulator with its `print_state` calls enabled.
+#[test]
+fn print_state_matches_reference_format() {
+  let name = "jas21ba_test4.mc.2.2.2";
+  let output = Command::new(env!("CARGO_BIN_EXE_cda3100_lc_sim"))
+    .arg("--print-state")
+    .arg(common::fixture("machine_code", name))
+    .args(["2", "2", "2"])
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+
+  let expected =
+    fs::read_to_string(common::fixture("print_state", &format!("{}.out", name))).unwrap();
+  assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
@@ -0,0 +1,78 @@
+use cda3100_lc_sim::cache::{AccessKind, Cache};
+use cda3100_lc_sim::replacement::{new_policy, ReplacementKind};
+
+const A: usize = 0;
+const B: usize = 1;
+const C: usize = 2;
+const D: usize = 3;
+const E: usize = 4;
+
+/// Fills a single 4 block set with A-D, uses them so each policy prefers a different victim,
+/// then loads E and returns the address that got evicted
+fn evicted_by(kind: ReplacementKind, seed: u64) -> usize {
+  let policy = new_policy(kind, 1, 4, seed);
+  let mut cache = Cache::with_policy(1, 1, 4, policy);
+  let mut memory = vec![0; 16];
+
+  // A is the oldest block, B the least recently used, C the least frequently used, and
+  // the pseudo LRU tree ends up pointing at D
+  let trace = [A, B, C, D, B, B, B, D, D, C, A, A];
+  for address in trace {
+    cache.get_value(address, &mut memory, AccessKind::Load);
+  }
+  cache.get_value(E, &mut memory, AccessKind::Load);
+
+  let evicted: Vec<usize> = [A, B, C, D]
+    .into_iter()
+    .filter(|address| cache.peek(*address).is_none())
+    .collect();
+  assert_eq!(evicted.len(), 1);
+  evicted[0]
+}
+
+#[test]
+fn fifo_evicts_oldest_block() {
+  assert_eq!(evicted_by(ReplacementKind::Fifo, 1), A);
+}
+
+#[test]
+fn lru_evicts_least_recently_used_block() {
+  assert_eq!(evicted_by(ReplacementKind::Lru, 1), B);
+}
+
+#[test]
+fn lfu_evicts_least_frequently_used_block() {
+  assert_eq!(evicted_by(ReplacementKind::Lfu, 1), C);
+}
+
+#[test]
+fn plru_follows_tree_bits() {
+  assert_eq!(evicted_by(ReplacementKind::Plru, 1), D);
+}
+
+#[test]
+fn random_is_repeatable_for_a_seed() {
+  for seed in 0..16 {
+    assert_eq!(
+      evicted_by(ReplacementKind::Random, seed),
+      evicted_by(ReplacementKind::Random, seed)
+    );
+  }
+
+  // Different seeds should not all pick the same block
+  let victims: Vec<usize> = (0..16)
+    .map(|seed| evicted_by(ReplacementKind::Random, seed))
+    .collect();
+  assert!(victims.iter().any(|victim| *victim != victims[0]));
+}
+
+#[test]
+fn default_cache_uses_lru() {
+  let mut cache = Cache::new(1, 1, 2);
+  let mut memory = vec![0; 4];
+  for address in [0, 1, 0, 2] {
+    cache.get_value(address, &mut memory, AccessKind::Load);
+  }
+  assert_eq!(cache.peek(0), Some(0));
+  assert_eq!(cache.peek(1), None);
+}
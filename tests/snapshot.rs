@@ -0,0 +1,186 @@
+mod common;
+
+use cda3100_lc_sim::assembler::Program;
+use cda3100_lc_sim::cache::{Cache, CacheConfig, WriteMissPolicy, WritePolicy};
+use cda3100_lc_sim::replacement::ReplacementKind;
+use cda3100_lc_sim::snapshot::Snapshot;
+use cda3100_lc_sim::{Machine, SimError};
+
+// A FIFO L1 with a write buffer in front of a random L2, so the snapshot has policy counters,
+// a random number generator and pending writes to carry over
+fn machine(program: &Program) -> Machine {
+  let l1 = CacheConfig {
+    replacement: ReplacementKind::Fifo,
+    write_policy: WritePolicy::Through,
+    write_miss_policy: WriteMissPolicy::NoAllocate,
+    write_buffer_size: 2,
+    ..CacheConfig::new(2, 2, 2)
+  };
+  let l2 = CacheConfig {
+    replacement: ReplacementKind::Random,
+    ..CacheConfig::new(2, 2, 4)
+  };
+  let mut machine = Machine::with_hierarchy(vec![Cache::from_config(&l1), Cache::from_config(&l2)]);
+  machine.load(program).unwrap();
+  machine
+}
+
+#[test]
+fn resuming_from_a_snapshot_ends_the_same_as_running_straight_through() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut straight = machine(&program);
+  straight.run().unwrap();
+
+  for stop in [1, 40, 93] {
+    let mut first = machine(&program);
+    first
+      .run_until(|machine| machine.instructions_executed() == stop)
+      .unwrap();
+    let text = first.snapshot().to_text();
+
+    let mut resumed = machine(&program);
+    resumed.restore(Snapshot::parse(&text).unwrap()).unwrap();
+    assert_eq!(resumed.instructions_executed(), stop);
+    resumed.run().unwrap();
+
+    assert_eq!(resumed.registers(), straight.registers());
+    assert_eq!(
+      resumed.memory_system().memory(),
+      straight.memory_system().memory()
+    );
+    assert_eq!(
+      resumed.instructions_executed(),
+      straight.instructions_executed()
+    );
+    for (resumed, straight) in resumed
+      .memory_system()
+      .caches()
+      .iter()
+      .zip(straight.memory_system().caches())
+    {
+      assert_eq!(resumed.stats(), straight.stats(), "stopped after {}", stop);
+      assert_eq!(resumed.save(), straight.save(), "stopped after {}", stop);
+    }
+  }
+}
+
+#[test]
+fn text_format_round_trips() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut machine = machine(&program);
+  machine
+    .run_until(|machine| machine.instructions_executed() == 25)
+    .unwrap();
+  let snapshot = machine.snapshot();
+  let text = snapshot.to_text();
+  assert!(text.starts_with("LC3100 snapshot\npc "));
+  assert_eq!(Snapshot::parse(&text).unwrap(), snapshot);
+}
+
+#[test]
+fn snapshots_only_restore_into_matching_machines() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut source = machine(&program);
+  source
+    .run_until(|machine| machine.instructions_executed() == 10)
+    .unwrap();
+  let snapshot = source.snapshot();
+
+  // Different levels
+  let mut single = Machine::new(Cache::new(2, 2, 2));
+  assert!(matches!(
+    single.restore(snapshot.clone()),
+    Err(SimError::InvalidCache(_))
+  ));
+  // Same levels with a different L1 geometry, nothing is changed
+  let mut other = Machine::with_hierarchy(vec![Cache::new(2, 4, 1), Cache::new(2, 2, 4)]);
+  other.load(&program).unwrap();
+  assert!(matches!(
+    other.restore(snapshot.clone()),
+    Err(SimError::InvalidCache(_))
+  ));
+  assert_eq!(other.pc(), 0);
+  assert_eq!(other.memory_system().caches()[1].stats().reads(), 0);
+
+  let text = snapshot.to_text().replace("halted false", "halted maybe");
+  assert!(matches!(
+    Snapshot::parse(&text),
+    Err(SimError::BadSnapshot { line: 4, .. })
+  ));
+  let truncated: String = snapshot
+    .to_text()
+    .lines()
+    .take(12)
+    .collect::<Vec<_>>()
+    .join("\n");
+  assert!(matches!(
+    Snapshot::parse(&truncated),
+    Err(SimError::BadSnapshot { .. })
+  ));
+}
+
+/// Replaces the first line of `text` starting with `prefix`, returning the new text and the
+/// number of the line replaced
+fn replace_line(text: &str, prefix: &str, line: &str) -> (String, usize) {
+  let mut lines: Vec<&str> = text.lines().collect();
+  let index = lines
+    .iter()
+    .position(|text| text.starts_with(prefix))
+    .unwrap();
+  lines[index] = line;
+  (lines.join("\n"), index + 1)
+}
+
+#[test]
+fn snapshots_reaching_outside_of_memory_are_rejected() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut source = machine(&program);
+  source
+    .run_until(|machine| machine.instructions_executed() == 10)
+    .unwrap();
+  let text = source.snapshot().to_text();
+
+  for (prefix, line) in [
+    ("mem ", "mem 18446744073709551615 1 2"),
+    ("program-length", "program-length 65537"),
+    ("write-buffer", "write-buffer 65536 7"),
+    ("block", "block 1 0 65536 0 1 2"),
+    ("block", "block 1 0 0 65535 1 2"),
+    ("block", "block 1 0 0 -1 1 2"),
+  ] {
+    let (bad, line_number) = replace_line(&text, prefix, line);
+    match Snapshot::parse(&bad) {
+      Err(SimError::BadSnapshot { line, .. }) => assert_eq!(line, line_number, "{}", line),
+      _ => panic!("expected `{}` to be rejected", line),
+    }
+  }
+}
+
+#[test]
+fn restore_rejects_snapshots_the_machine_could_not_be_in() {
+  let program = common::assembly("jas21ba_test3.as.2.2.2");
+  let mut source = machine(&program);
+  source
+    .run_until(|machine| machine.instructions_executed() == 10)
+    .unwrap();
+  let snapshot = source.snapshot();
+
+  let mut short = snapshot.clone();
+  short.memory.truncate(100);
+  assert!(matches!(
+    machine(&program).restore(short),
+    Err(SimError::InvalidSnapshot(_))
+  ));
+
+  // The L2's random number generator can never get stuck at zero
+  let mut stuck = snapshot.clone();
+  for (_, policy_state) in stuck.caches[1].1.sets.iter_mut() {
+    policy_state[0] = 0;
+  }
+  let mut restored = machine(&program);
+  assert!(matches!(
+    restored.restore(stuck),
+    Err(SimError::InvalidCache(_))
+  ));
+  assert_eq!(restored.pc(), 0);
+}
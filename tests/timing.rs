@@ -0,0 +1,85 @@
+mod common;
+
+use cda3100_lc_sim::cache::{Cache, CacheConfig};
+use cda3100_lc_sim::timing::MemoryTiming;
+use cda3100_lc_sim::Machine;
+
+fn timed_machine(config: &CacheConfig) -> Machine {
+  let mut machine = Machine::new(Cache::from_config(config));
+  machine.set_memory_timing(MemoryTiming {
+    latency: 7,
+    word_cycles: 3,
+  });
+  machine
+    .load(&common::assembly("jas21ba_test4.as.2.2.2"))
+    .unwrap();
+  machine
+}
+
+#[test]
+fn cycles_add_up_from_the_statistics() {
+  let config = CacheConfig {
+    hit_latency: 2,
+    ..CacheConfig::new(2, 2, 2)
+  };
+  let mut machine = timed_machine(&config);
+  machine.run().unwrap();
+
+  let stats = machine.memory_system().cache().stats();
+  let accesses = stats.reads() + stats.writes();
+  // Write-back and write-allocate, so memory only sees block fills and write-backs
+  let transfers = stats.misses() + stats.dirty_evictions + stats.halt_writebacks;
+  let words = stats.words_from_memory + stats.words_to_memory;
+  let memory_cycles = transfers * 7 + words * 3;
+
+  let timing = machine.timing();
+  assert_eq!(timing.accesses, accesses);
+  assert_eq!(timing.accesses, 18);
+  assert_eq!(timing.memory_cycles, memory_cycles);
+  assert_eq!(timing.cycles, accesses * 2 + memory_cycles);
+  assert_eq!(timing.instructions, Some(10));
+  assert_eq!(timing.cpi(), Some(timing.cycles as f64 / 10.0));
+  assert_eq!(timing.amat(), timing.cycles as f64 / 18.0);
+}
+
+#[test]
+fn lower_levels_add_their_own_latency() {
+  let l2 = CacheConfig {
+    hit_latency: 5,
+    ..CacheConfig::new(4, 2, 2)
+  };
+  let mut machine = Machine::with_hierarchy(vec![Cache::new(2, 2, 2), Cache::from_config(&l2)]);
+  machine
+    .load(&common::assembly("jas21ba_test4.as.2.2.2"))
+    .unwrap();
+  machine.run().unwrap();
+
+  let l1 = machine.memory_system().caches()[0].stats();
+  let l2 = machine.memory_system().caches()[1].stats();
+  // The L2 is accessed once for every block the L1 fills or writes back
+  let l1_transfers = l1.misses() + l1.dirty_evictions + l1.halt_writebacks;
+  assert_eq!(l2.access_cycles, 5 * l1_transfers);
+  assert_eq!(
+    machine.timing().cycles,
+    l1.access_cycles + l2.access_cycles + machine.timing().memory_cycles
+  );
+}
+
+#[test]
+fn stepping_back_takes_the_cycles_back() {
+  let mut machine = timed_machine(&CacheConfig::new(2, 2, 2));
+  machine.record_history(true);
+  machine.run().unwrap();
+  assert!(machine.timing().cycles > 0);
+
+  machine.step_back(machine.history_len());
+  let timing = machine.timing();
+  assert_eq!((timing.cycles, timing.memory_cycles), (0, 0));
+}
+
+#[test]
+fn parses_hit_latency_in_cache_specs() {
+  let config: CacheConfig = "4.4.2,hit-latency=3".parse().unwrap();
+  assert_eq!(config.hit_latency, 3);
+  assert_eq!(CacheConfig::new(1, 1, 1).hit_latency, 1);
+}
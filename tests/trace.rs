@@ -0,0 +1,82 @@
+mod common;
+
+use cda3100_lc_sim::cache::{AccessKind, Cache};
+use cda3100_lc_sim::trace::{din_line, TraceFormat, TraceRecord, CSV_HEADER};
+use cda3100_lc_sim::Machine;
+
+fn trace(name: &str) -> Vec<TraceRecord> {
+  let mut machine = Machine::new(Cache::new(2, 2, 2));
+  machine.load(&common::assembly(name)).unwrap();
+  common::run_traced(&mut machine)
+}
+
+#[test]
+fn records_every_instruction() {
+  let records = trace("jas21ba_test6.as.2.2.2");
+  assert_eq!(records.len(), 10);
+  let cycles: Vec<usize> = records.iter().map(|record| record.cycle).collect();
+  assert_eq!(cycles, (1..=10).collect::<Vec<_>>());
+  let pcs: Vec<u32> = records.iter().map(|record| record.pc).collect();
+  assert_eq!(pcs, [0, 1, 2, 6, 7, 3, 6, 7, 4, 5]);
+
+  // lw 0 1 8 misses on both the fetch and the load
+  let first = records[0];
+  assert_eq!(first.instruction.to_string(), "lw 0 1 8");
+  assert_eq!(first.register_written, Some((1, 5)));
+  assert_eq!(first.memory_access, Some((AccessKind::Load, 8)));
+  assert!(!first.fetch_hit);
+  assert_eq!(first.data_hit, Some(false));
+
+  // jalr 4 7 links through register 7 and doesn't touch data
+  let call = records[2];
+  assert_eq!(call.register_written, Some((7, 3)));
+  assert_eq!(call.memory_access, None);
+  assert_eq!(call.data_hit, None);
+
+  let store = records[8];
+  assert_eq!(store.register_written, None);
+  assert_eq!(store.memory_access, Some((AccessKind::Store, 10)));
+}
+
+#[test]
+fn formats_json_lines_and_csv() {
+  let records = trace("jas21ba_test6.as.2.2.2");
+  assert_eq!(
+    records[0].format(TraceFormat::Jsonl),
+    concat!(
+      "{\"cycle\":1,\"pc\":0,\"word\":8454152,\"mnemonic\":\"lw\",\"instruction\":\"lw 0 1 8\",",
+      "\"registers_written\":{\"1\":5},\"access\":\"load\",\"address\":8,",
+      "\"fetch\":\"miss\",\"data\":\"miss\"}"
+    )
+  );
+  assert_eq!(
+    records[9].format(TraceFormat::Jsonl),
+    concat!(
+      "{\"cycle\":10,\"pc\":5,\"word\":25165824,\"mnemonic\":\"halt\",\"instruction\":\"halt\",",
+      "\"registers_written\":{},\"access\":null,\"address\":null,\"fetch\":\"hit\",\"data\":null}"
+    )
+  );
+
+  let columns = CSV_HEADER.split(',').count();
+  for record in records.iter() {
+    assert_eq!(record.to_csv().split(',').count(), columns);
+  }
+  assert_eq!(
+    records[8].to_csv(),
+    "9,4,12648458,sw,sw 0 1 10,,,store,10,miss,miss"
+  );
+}
+
+#[test]
+fn din_lists_every_l1_access() {
+  let records = trace("jas21ba_test6.as.2.2.2");
+  let din: Vec<String> = records
+    .iter()
+    .flat_map(TraceRecord::accesses)
+    .map(|(kind, address)| din_line(kind, address))
+    .collect();
+  // 10 fetches, 2 loads and 1 store
+  assert_eq!(din.len(), 13);
+  assert_eq!(&din[..4], &["2 0", "0 20", "2 4", "0 24"]);
+  assert_eq!(&din[11..], &["1 28", "2 14"]);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use cda3100_lc_sim::assembler::{assemble, Program};
+use cda3100_lc_sim::cache::{Cache, CacheConfig, WriteMissPolicy, WritePolicy};
+use cda3100_lc_sim::memory::MemorySystem;
+use cda3100_lc_sim::timing::MemoryTiming;
+use cda3100_lc_sim::Machine;
+
+fn load(source: &str, config: CacheConfig) -> (Machine, Program) {
+  let program = assemble(source).unwrap();
+  let mut machine = Machine::new(Cache::from_config(&config));
+  machine.load(&program).unwrap();
+  (machine, program)
+}
+
+fn load_test4(write_policy: WritePolicy, write_miss_policy: WriteMissPolicy) -> Machine {
+  let config = CacheConfig {
+    write_policy,
+    write_miss_policy,
+    ..CacheConfig::new(2, 2, 2)
+  };
+  let mut machine = Machine::new(Cache::from_config(&config));
+  machine
+    .load(&common::assembly("jas21ba_test4.as.2.2.2"))
+    .unwrap();
+  machine.run().unwrap();
+  machine
+}
+
+// Stores to `value` twice before anything reads its block
+const REPEATED_STORES: &str = "\tlw 0 1 one
+\tsw 0 1 value
+\tadd 1 1 1
+\tsw 0 1 value
+\tlw 0 2 value
+\thalt
+one\t.fill 1
+value\t.fill 0
+";
+
+#[test]
+fn write_through_sends_each_store_to_memory() {
+  let machine = load_test4(WritePolicy::Through, WriteMissPolicy::Allocate);
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!(stats.write_throughs, 2);
+  assert_eq!(stats.words_to_memory, 2);
+  // Nothing is ever dirty so there is nothing left to write back
+  assert_eq!(stats.halt_writebacks, 0);
+  assert_eq!(stats.dirty_evictions, 0);
+  assert_eq!(
+    &machine.memory_system().memory()[10..16],
+    &[1, 2, 3, 4, 5, 3]
+  );
+}
+
+#[test]
+fn no_write_allocate_skips_the_cache_on_store_misses() {
+  let machine = load_test4(WritePolicy::Back, WriteMissPolicy::NoAllocate);
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!((stats.store_hits, stats.store_misses), (1, 1));
+  // The missing store's block is never brought in
+  assert_eq!(stats.words_from_memory, 16);
+  assert_eq!(stats.write_throughs, 1);
+  assert_eq!(stats.halt_writebacks, 1);
+  assert_eq!(
+    &machine.memory_system().memory()[10..16],
+    &[1, 2, 3, 4, 5, 3]
+  );
+}
+
+#[test]
+fn write_buffer_coalesces_and_drains_before_reads() {
+  let config = CacheConfig {
+    write_policy: WritePolicy::Through,
+    write_miss_policy: WriteMissPolicy::NoAllocate,
+    write_buffer_size: 2,
+    ..CacheConfig::new(1, 1, 1)
+  };
+  let (mut machine, program) = load(REPEATED_STORES, config);
+  let value = program.label_address("value").unwrap();
+
+  machine.run_until(|machine| machine.pc() == 4).unwrap();
+  // The second store replaced the first one while it was still in the buffer
+  assert_eq!(
+    machine
+      .memory_system()
+      .cache()
+      .stats()
+      .write_buffer_coalesced,
+    1
+  );
+  assert_eq!(machine.memory_system().memory()[value], 0);
+  assert_eq!(machine.memory_system().peek_memory(value), 2);
+
+  machine.run().unwrap();
+  // Loading the block drained the buffered store first
+  assert_eq!(machine.register(2), 2);
+  assert_eq!(machine.memory_system().memory()[value], 2);
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!(stats.write_buffer_drains, 1);
+  assert_eq!(stats.words_to_memory, 1);
+}
+
+#[test]
+fn default_config_is_write_back_write_allocate() {
+  let machine = load_test4(WritePolicy::Back, WriteMissPolicy::Allocate);
+  let stats = machine.memory_system().cache().stats();
+  assert_eq!(stats.write_throughs, 0);
+  assert_eq!(stats.halt_writebacks, 2);
+  assert_eq!(stats.words_to_memory, 4);
+}
+
+#[test]
+fn flushing_twice_writes_dirty_blocks_back_once() {
+  let mut memory = MemorySystem::new(vec![Cache::new(2, 1, 1), Cache::new(2, 2, 1)], None);
+  memory.set_memory_timing(MemoryTiming {
+    latency: 10,
+    word_cycles: 1,
+  });
+  memory.store_word(4, 7);
+  memory.flush();
+  let l1 = memory.caches()[0].stats().clone();
+  let l2 = memory.caches()[1].stats().clone();
+  let cycles = memory.timing().memory_cycles;
+
+  memory.flush();
+  assert_eq!(memory.memory()[4], 7);
+  assert_eq!(l1.halt_writebacks, 1);
+  assert_eq!(l2.halt_writebacks, 1);
+  assert_eq!(memory.caches()[0].stats(), &l1);
+  assert_eq!(memory.caches()[1].stats(), &l2);
+  assert_eq!(memory.timing().memory_cycles, cycles);
+}